@@ -0,0 +1,674 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::Permissions;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use sha2::{Digest, Sha256};
+
+use crate::error::ChiconError;
+use crate::{
+    ChecksumAlgorithm, DirEntry, File, FileSystem, FileType, Metadata, OsFile, OsFileSystem,
+    VirtualPath,
+};
+
+const CACHE_FILE_EXTENSION: &str = "cache";
+// Subdirectory of the cache directory holding a directory of cached files per instance
+const CACHE_SUBDIR: &str = "chicon-cache";
+
+// Number of instances created by this process, part of their directory name
+static INSTANCE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Options of a `CachedFileSystem`
+pub struct CacheOptions {
+    max_size: u64,
+    listing_ttl: Duration,
+}
+
+impl Default for CacheOptions {
+    fn default() -> Self {
+        CacheOptions {
+            max_size: 1024 * 1024 * 1024,
+            listing_ttl: Duration::from_secs(60),
+        }
+    }
+}
+
+impl CacheOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maximum size in bytes of the cached contents, least recently used files are evicted above it, 1GB by default
+    pub fn max_size(mut self, max_size: u64) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Duration during which a directory listing or a cached file is served without asking the remote filesystem,
+    /// 60 seconds by default
+    pub fn listing_ttl(mut self, listing_ttl: Duration) -> Self {
+        self.listing_ttl = listing_ttl;
+        self
+    }
+}
+
+struct CacheEntry {
+    local_path: PathBuf,
+    len: u64,
+    modified: Option<SystemTime>,
+    etag: Option<String>,
+    last_used: u64,
+    validated_at: Instant,
+}
+
+impl CacheEntry {
+    fn is_fresh(&self, metadata: &Metadata) -> bool {
+        if let (Some(etag), Some(remote_etag)) = (&self.etag, &metadata.etag) {
+            return etag == remote_etag;
+        }
+
+        self.modified.is_some() && self.modified == metadata.modified && self.len == metadata.len
+    }
+}
+
+struct CachedListing {
+    dir_entries: Vec<(PathBuf, FileType)>,
+    fetched_at: Instant,
+}
+
+#[derive(Default)]
+struct CacheState {
+    files: HashMap<PathBuf, CacheEntry>,
+    listings: HashMap<PathBuf, CachedListing>,
+    size: u64,
+    clock: u64,
+}
+
+/// Structure implementing `FileSystem` trait to keep in a local directory the files downloaded from a remote filesystem.
+///
+/// A local copy is served as is during the listing TTL, then `open_file` fetches the remote metadata and keeps serving it while its ETag,
+/// or its modification date and size, still match. Directory listings are kept in memory during the same duration. Any modification made
+/// through the wrapper invalidates the related entries, modifications made directly on the remote filesystem are only noticed when the
+/// local copy is validated again.
+/// Files served from the cache are read-only, use `create_file` to replace their content.
+pub struct CachedFileSystem<R: FileSystem> {
+    remote: R,
+    local: OsFileSystem,
+    cache_dir: PathBuf,
+    options: CacheOptions,
+    state: RefCell<CacheState>,
+}
+
+impl<R: FileSystem> CachedFileSystem<R> {
+    /// Create the cache in a new directory of the `chicon-cache` subdirectory of `cache_dir`, removed when the
+    /// instance is dropped. Several instances, even in different processes, can share the same `cache_dir`.
+    /// Nothing else in `cache_dir` is touched, directories left by a process which didn't exit cleanly aren't removed.
+    pub fn new<P: AsRef<Path>>(
+        remote: R,
+        cache_dir: P,
+        options: CacheOptions,
+    ) -> Result<Self, ChiconError> {
+        let local = OsFileSystem::new();
+        let cache_dir = instance_dir(&local, &cache_dir.as_ref().join(CACHE_SUBDIR))?;
+
+        Ok(CachedFileSystem {
+            remote,
+            local,
+            cache_dir,
+            options,
+            state: RefCell::new(CacheState::default()),
+        })
+    }
+
+    /// Get a reference to the remote filesystem, modifications made through it bypass the cache
+    pub fn remote(&self) -> &R {
+        &self.remote
+    }
+
+    /// Remove every cached file and listing
+    pub fn clear(&self) -> Result<(), ChiconError> {
+        let mut state = self.state.try_borrow_mut()?;
+        for (_, entry) in state.files.drain() {
+            self.local.remove_file(&entry.local_path)?;
+        }
+        state.listings.clear();
+        state.size = 0;
+
+        Ok(())
+    }
+
+    fn local_path(&self, key: &Path) -> PathBuf {
+        let hash = hex::encode(Sha256::digest(key.to_string_lossy().as_bytes()));
+        self.cache_dir
+            .join(format!("{}.{}", hash, CACHE_FILE_EXTENSION))
+    }
+
+    fn remove_entry(&self, state: &mut CacheState, key: &Path) -> Result<(), ChiconError> {
+        if let Some(entry) = state.files.remove(key) {
+            state.size -= entry.len;
+            self.local.remove_file(&entry.local_path)?;
+        }
+
+        Ok(())
+    }
+
+    // Forget `path` and the listing of its parent
+    fn invalidate(&self, path: &VirtualPath) -> Result<(), ChiconError> {
+        let mut state = self.state.try_borrow_mut()?;
+        self.remove_entry(&mut state, path.relative())?;
+        state.listings.remove(path.relative());
+        if let Some(parent) = path.parent() {
+            state.listings.remove(parent.relative());
+        }
+
+        Ok(())
+    }
+
+    // Forget `path`, everything under it and the listing of its parent
+    fn invalidate_tree(&self, path: &VirtualPath) -> Result<(), ChiconError> {
+        let mut state = self.state.try_borrow_mut()?;
+        let keys: Vec<PathBuf> = state
+            .files
+            .keys()
+            .filter(|key| key.starts_with(path.relative()))
+            .cloned()
+            .collect();
+        for key in keys {
+            self.remove_entry(&mut state, &key)?;
+        }
+        state
+            .listings
+            .retain(|key, _| !key.starts_with(path.relative()));
+        if let Some(parent) = path.parent() {
+            state.listings.remove(parent.relative());
+        }
+
+        Ok(())
+    }
+
+    // Evict least recently used files until the cache fits in its maximum size
+    fn evict(&self, state: &mut CacheState) -> Result<(), ChiconError> {
+        while state.size > self.options.max_size {
+            let key = match state
+                .files
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+            {
+                Some(key) => key,
+                None => break,
+            };
+            self.remove_entry(state, &key)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<R: FileSystem> Drop for CachedFileSystem<R> {
+    fn drop(&mut self) {
+        let _ = self.local.remove_dir_all(&self.cache_dir);
+    }
+}
+
+// Create a directory of `cache_dir` belonging to a single instance
+fn instance_dir(local: &OsFileSystem, cache_dir: &Path) -> Result<PathBuf, ChiconError> {
+    local.create_dir_all(cache_dir)?;
+    loop {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.subsec_nanos())
+            .unwrap_or_default();
+        let dir = cache_dir.join(format!(
+            "{}-{}-{}",
+            std::process::id(),
+            INSTANCE_COUNT.fetch_add(1, Ordering::Relaxed),
+            nanos
+        ));
+        match local.create_dir(&dir) {
+            Err(ChiconError::IOError(ref err)) if err.kind() == io::ErrorKind::AlreadyExists => {}
+            res => return res.map(|_| dir),
+        }
+    }
+}
+
+impl<R> FileSystem for CachedFileSystem<R>
+where
+    R: FileSystem,
+    ChiconError: From<R::FSError>
+        + From<<R::File as File>::FSError>
+        + From<<R::DirEntry as DirEntry>::FSError>,
+{
+    type FSError = ChiconError;
+    type File = CachedFile<R::File>;
+    type DirEntry = CachedDirEntry;
+
+    fn chmod<P: AsRef<Path>>(&self, path: P, perm: Permissions) -> Result<(), Self::FSError> {
+        let path = VirtualPath::new(path)?;
+        self.invalidate(&path)?;
+        Ok(self.remote.chmod(path.to_path_buf(), perm)?)
+    }
+
+    fn create_file<P: AsRef<Path>>(&self, path: P) -> Result<Self::File, Self::FSError> {
+        let path = VirtualPath::new(path)?;
+        self.invalidate(&path)?;
+        Ok(CachedFile::Remote(
+            self.remote.create_file(path.to_path_buf())?,
+        ))
+    }
+
+    fn create_dir<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
+        let path = VirtualPath::new(path)?;
+        self.invalidate(&path)?;
+        Ok(self.remote.create_dir(path.to_path_buf())?)
+    }
+
+    fn create_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
+        let path = VirtualPath::new(path)?;
+        let mut ancestor = Some(path.clone());
+        while let Some(current) = ancestor {
+            self.invalidate(&current)?;
+            ancestor = current.parent();
+        }
+        Ok(self.remote.create_dir_all(path.to_path_buf())?)
+    }
+
+    fn open_file<P: AsRef<Path>>(&self, path: P) -> Result<Self::File, Self::FSError> {
+        let path = VirtualPath::new(path)?;
+        let key = path.relative();
+        let mut state = self.state.try_borrow_mut()?;
+        state.clock += 1;
+        let clock = state.clock;
+
+        // Served without asking the remote filesystem until the copy is older than the TTL
+        let ttl = self.options.listing_ttl;
+        let mut fresh_local_path = match state.files.get_mut(key) {
+            Some(entry) if entry.validated_at.elapsed() < ttl => {
+                entry.last_used = clock;
+                Some(entry.local_path.clone())
+            }
+            _ => None,
+        };
+        if let Some(local_path) = fresh_local_path {
+            return Ok(CachedFile::Local(self.local.open_file(local_path)?));
+        }
+
+        let metadata = self.remote.metadata(path.to_path_buf())?;
+        fresh_local_path = match state.files.get_mut(key) {
+            Some(entry) if entry.is_fresh(&metadata) => {
+                entry.last_used = clock;
+                entry.validated_at = Instant::now();
+                Some(entry.local_path.clone())
+            }
+            _ => None,
+        };
+        if let Some(local_path) = fresh_local_path {
+            return Ok(CachedFile::Local(self.local.open_file(local_path)?));
+        }
+
+        self.remove_entry(&mut state, key)?;
+        if metadata.len > self.options.max_size {
+            return Ok(CachedFile::Remote(
+                self.remote.open_file(path.to_path_buf())?,
+            ));
+        }
+
+        let local_path = self.local_path(key);
+        let len = {
+            let mut remote_file = self.remote.open_file(path.to_path_buf())?;
+            let mut local_file = self.local.create_file(&local_path)?;
+            let len = io::copy(&mut remote_file, &mut local_file)?;
+            local_file.sync_all()?;
+            len
+        };
+        state.files.insert(
+            PathBuf::from(key),
+            CacheEntry {
+                local_path: local_path.clone(),
+                len,
+                modified: metadata.modified,
+                etag: metadata.etag,
+                last_used: clock,
+                validated_at: Instant::now(),
+            },
+        );
+        state.size += len;
+        self.evict(&mut state)?;
+
+        Ok(CachedFile::Local(self.local.open_file(local_path)?))
+    }
+
+    fn read_dir<P: AsRef<Path>>(&self, path: P) -> Result<Vec<Self::DirEntry>, Self::FSError> {
+        let path = VirtualPath::new(path)?;
+        let key = path.relative();
+        {
+            let state = self.state.try_borrow()?;
+            if let Some(listing) = state.listings.get(key) {
+                if listing.fetched_at.elapsed() < self.options.listing_ttl {
+                    return Ok(listing
+                        .dir_entries
+                        .iter()
+                        .map(|(path, file_type)| CachedDirEntry {
+                            path: path.clone(),
+                            file_type: file_type.clone(),
+                        })
+                        .collect());
+                }
+            }
+        }
+
+        let mut dir_entries = Vec::new();
+        for entry in self.remote.read_dir(path.to_path_buf())? {
+            dir_entries.push((entry.path()?, entry.file_type()?));
+        }
+        self.state.try_borrow_mut()?.listings.insert(
+            PathBuf::from(key),
+            CachedListing {
+                dir_entries: dir_entries.clone(),
+                fetched_at: Instant::now(),
+            },
+        );
+
+        Ok(dir_entries
+            .into_iter()
+            .map(|(path, file_type)| CachedDirEntry { path, file_type })
+            .collect())
+    }
+
+    fn remove_file<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
+        let path = VirtualPath::new(path)?;
+        self.invalidate(&path)?;
+        Ok(self.remote.remove_file(path.to_path_buf())?)
+    }
+
+    fn remove_dir<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
+        let path = VirtualPath::new(path)?;
+        self.invalidate(&path)?;
+        Ok(self.remote.remove_dir(path.to_path_buf())?)
+    }
+
+    fn remove_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
+        let path = VirtualPath::new(path)?;
+        self.invalidate_tree(&path)?;
+        Ok(self.remote.remove_dir_all(path.to_path_buf())?)
+    }
+
+    fn rename<P: AsRef<Path>>(&self, from: P, to: P) -> Result<(), Self::FSError> {
+        let from = VirtualPath::new(from)?;
+        let to = VirtualPath::new(to)?;
+        self.invalidate_tree(&from)?;
+        self.invalidate_tree(&to)?;
+        Ok(self.remote.rename(from.to_path_buf(), to.to_path_buf())?)
+    }
+
+    fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata, Self::FSError> {
+        Ok(self.remote.metadata(path)?)
+    }
+
+    fn symlink<P: AsRef<Path>>(&self, target: P, link: P) -> Result<(), Self::FSError> {
+        let link = VirtualPath::new(link)?;
+        self.invalidate(&link)?;
+        Ok(self
+            .remote
+            .symlink(PathBuf::from(target.as_ref()), link.to_path_buf())?)
+    }
+
+    fn read_link<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Self::FSError> {
+        Ok(self.remote.read_link(path)?)
+    }
+
+    fn symlink_metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata, Self::FSError> {
+        Ok(self.remote.symlink_metadata(path)?)
+    }
+
+    fn canonicalize<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Self::FSError> {
+        Ok(self.remote.canonicalize(path)?)
+    }
+
+    fn set_modified<P: AsRef<Path>>(
+        &self,
+        path: P,
+        modified: SystemTime,
+    ) -> Result<(), Self::FSError> {
+        let path = VirtualPath::new(path)?;
+        self.invalidate(&path)?;
+        Ok(self.remote.set_modified(path.to_path_buf(), modified)?)
+    }
+
+    fn copy<P: AsRef<Path>>(&self, from: P, to: P) -> Result<(), Self::FSError> {
+        let to = VirtualPath::new(to)?;
+        self.invalidate(&to)?;
+        Ok(self
+            .remote
+            .copy(from.as_ref(), to.to_path_buf().as_path())?)
+    }
+
+    fn copy_dir_all<P: AsRef<Path>>(&self, from: P, to: P) -> Result<(), Self::FSError> {
+        let to = VirtualPath::new(to)?;
+        self.invalidate_tree(&to)?;
+        Ok(self
+            .remote
+            .copy_dir_all(from.as_ref(), to.to_path_buf().as_path())?)
+    }
+
+    fn checksum<P: AsRef<Path>>(
+        &self,
+        path: P,
+        algorithm: ChecksumAlgorithm,
+    ) -> Result<String, Self::FSError> {
+        Ok(self.remote.checksum(path, algorithm)?)
+    }
+}
+
+/// Structure implementing `File` trait to represent a file of a `CachedFileSystem`, either a read-only local copy or a remote file
+pub enum CachedFile<F: File> {
+    Local(OsFile),
+    Remote(F),
+}
+
+impl<F> File for CachedFile<F>
+where
+    F: File,
+    ChiconError: From<F::FSError>,
+{
+    type FSError = ChiconError;
+
+    fn sync_all(&mut self) -> Result<(), Self::FSError> {
+        match self {
+            CachedFile::Local(_) => Ok(()),
+            CachedFile::Remote(file) => Ok(file.sync_all()?),
+        }
+    }
+}
+
+impl<F: File> Read for CachedFile<F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            CachedFile::Local(file) => file.read(buf),
+            CachedFile::Remote(file) => file.read(buf),
+        }
+    }
+}
+
+impl<F: File> Write for CachedFile<F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            CachedFile::Local(_) => Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "file served from the cache, use create_file to replace it",
+            )),
+            CachedFile::Remote(file) => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            CachedFile::Local(_) => Ok(()),
+            CachedFile::Remote(file) => file.flush(),
+        }
+    }
+}
+
+impl<F: File> Seek for CachedFile<F> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            CachedFile::Local(file) => file.seek(pos),
+            CachedFile::Remote(file) => file.seek(pos),
+        }
+    }
+}
+
+/// Structure implementing `DirEntry` trait to represent an entry of a listing of a `CachedFileSystem`
+pub struct CachedDirEntry {
+    path: PathBuf,
+    file_type: FileType,
+}
+
+impl DirEntry for CachedDirEntry {
+    type FSError = ChiconError;
+
+    fn path(&self) -> Result<PathBuf, Self::FSError> {
+        Ok(self.path.clone())
+    }
+
+    fn file_type(&self) -> Result<FileType, Self::FSError> {
+        Ok(self.file_type.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemFileSystem;
+
+    fn read_content(cached_fs: &CachedFileSystem<MemFileSystem>, path: &str) -> String {
+        let mut content = String::new();
+        cached_fs
+            .open_file(path)
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        content
+    }
+
+    fn cached_files(cached_fs: &CachedFileSystem<MemFileSystem>) -> usize {
+        std::fs::read_dir(&cached_fs.cache_dir).unwrap().count()
+    }
+
+    #[test]
+    fn test_cached_open_file() {
+        let mem_fs = MemFileSystem::new();
+        mem_fs.create_dir_all("share/test").unwrap();
+        {
+            let mut file = mem_fs.create_file("share/test/myfile").unwrap();
+            file.write_all(b"coucoutoi").unwrap();
+        }
+        std::fs::create_dir_all("testcachedfs").unwrap();
+        std::fs::write("testcachedfs/mine.cache", b"mine").unwrap();
+        let cached_fs = CachedFileSystem::new(
+            mem_fs,
+            "testcachedfs",
+            CacheOptions::new().listing_ttl(Duration::from_secs(1)),
+        )
+        .unwrap();
+        assert!(Path::new("testcachedfs/mine.cache").exists());
+
+        assert_eq!(read_content(&cached_fs, "share/test/myfile"), "coucoutoi");
+        assert_eq!(cached_files(&cached_fs), 1);
+        let mut file = cached_fs.open_file("share/test/myfile").unwrap();
+        assert!(file.write_all(b"nope").is_err());
+
+        {
+            let mut file = cached_fs.create_file("share/test/myfile").unwrap();
+            file.write_all(b"updated").unwrap();
+        }
+        assert_eq!(cached_files(&cached_fs), 0);
+        assert_eq!(read_content(&cached_fs, "share/test/myfile"), "updated");
+
+        // Modified behind the cache, the local copy is served until the TTL passes and
+        // then doesn't match the remote metadata anymore
+        std::thread::sleep(Duration::from_millis(10));
+        {
+            let mut file = cached_fs.remote().create_file("share/test/myfile").unwrap();
+            file.write_all(b"from remote").unwrap();
+        }
+        assert_eq!(read_content(&cached_fs, "share/test/myfile"), "updated");
+        std::thread::sleep(Duration::from_millis(1100));
+        assert_eq!(read_content(&cached_fs, "share/test/myfile"), "from remote");
+
+        cached_fs.clear().unwrap();
+        assert_eq!(cached_files(&cached_fs), 0);
+        std::fs::remove_dir_all("testcachedfs").unwrap();
+    }
+
+    #[test]
+    fn test_cached_eviction_and_listing() {
+        let mem_fs = MemFileSystem::new();
+        mem_fs.create_dir_all("share/test").unwrap();
+        for name in &["first", "second"] {
+            let mut file = mem_fs.create_file(format!("share/test/{}", name)).unwrap();
+            file.write_all(b"coucou").unwrap();
+        }
+        let cached_fs = CachedFileSystem::new(
+            mem_fs,
+            "testcachedfseviction",
+            CacheOptions::new().max_size(10),
+        )
+        .unwrap();
+
+        read_content(&cached_fs, "share/test/first");
+        read_content(&cached_fs, "share/test/second");
+        assert_eq!(cached_files(&cached_fs), 1);
+        assert_eq!(read_content(&cached_fs, "share/test/first"), "coucou");
+
+        assert_eq!(cached_fs.read_dir("share/test").unwrap().len(), 2);
+        cached_fs.remote().create_file("share/test/third").unwrap();
+        assert_eq!(cached_fs.read_dir("share/test").unwrap().len(), 2);
+        cached_fs.remove_file("share/test/first").unwrap();
+        assert_eq!(cached_fs.read_dir("share/test").unwrap().len(), 2);
+
+        std::fs::remove_dir_all("testcachedfseviction").unwrap();
+    }
+
+    #[test]
+    fn test_cached_shared_dir() {
+        let remotes: Vec<MemFileSystem> = ["first", "second"]
+            .iter()
+            .map(|content| {
+                let mem_fs = MemFileSystem::new();
+                let mut file = mem_fs.create_file("myfile").unwrap();
+                file.write_all(content.as_bytes()).unwrap();
+                mem_fs
+            })
+            .collect();
+        let mut cached = remotes
+            .into_iter()
+            .map(|mem_fs| {
+                CachedFileSystem::new(mem_fs, "testcachedfsshared", CacheOptions::new()).unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(read_content(&cached[0], "myfile"), "first");
+        assert_eq!(read_content(&cached[1], "myfile"), "second");
+        let third = CachedFileSystem::new(
+            MemFileSystem::new(),
+            "testcachedfsshared",
+            CacheOptions::new(),
+        )
+        .unwrap();
+        assert_eq!(cached_files(&cached[0]), 1);
+        assert_eq!(read_content(&cached[0], "myfile"), "first");
+
+        let first_dir = cached[0].cache_dir.clone();
+        cached.remove(0);
+        assert!(!first_dir.exists());
+        assert_eq!(read_content(&cached[0], "myfile"), "second");
+
+        drop(third);
+        drop(cached);
+        std::fs::remove_dir_all("testcachedfsshared").unwrap();
+    }
+}
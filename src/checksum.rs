@@ -0,0 +1,73 @@
+use std::fmt;
+use std::io::Read;
+
+use md5::{Digest, Md5};
+use sha2::Sha256;
+
+/// Hash algorithms available to compute the checksum of a file
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChecksumAlgorithm {
+    Md5,
+    Sha256,
+    Crc32c,
+}
+
+impl fmt::Display for ChecksumAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChecksumAlgorithm::Md5 => write!(f, "md5"),
+            ChecksumAlgorithm::Sha256 => write!(f, "sha256"),
+            ChecksumAlgorithm::Crc32c => write!(f, "crc32c"),
+        }
+    }
+}
+
+/// Compute the checksum of the whole content of `reader` as a lowercase hexadecimal string
+pub(crate) fn hash_reader<R: Read>(
+    reader: &mut R,
+    algorithm: ChecksumAlgorithm,
+) -> Result<String, std::io::Error> {
+    let mut md5 = Md5::new();
+    let mut sha256 = Sha256::new();
+    let mut crc32c: u32 = 0;
+
+    let mut buffer = [0; 64 * 1024];
+    loop {
+        let nb = reader.read(&mut buffer)?;
+        if nb == 0 {
+            break;
+        }
+        match algorithm {
+            ChecksumAlgorithm::Md5 => md5.update(&buffer[..nb]),
+            ChecksumAlgorithm::Sha256 => sha256.update(&buffer[..nb]),
+            ChecksumAlgorithm::Crc32c => crc32c = crc32c::crc32c_append(crc32c, &buffer[..nb]),
+        }
+    }
+
+    Ok(match algorithm {
+        ChecksumAlgorithm::Md5 => hex::encode(md5.finalize()),
+        ChecksumAlgorithm::Sha256 => hex::encode(sha256.finalize()),
+        ChecksumAlgorithm::Crc32c => format!("{:08x}", crc32c),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_reader() {
+        assert_eq!(
+            hash_reader(&mut "coucoutoi".as_bytes(), ChecksumAlgorithm::Md5).unwrap(),
+            String::from("9b7c77a17e4906f5ed3fb8afdc129cfb")
+        );
+        assert_eq!(
+            hash_reader(&mut "".as_bytes(), ChecksumAlgorithm::Sha256).unwrap(),
+            String::from("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855")
+        );
+        assert_eq!(
+            hash_reader(&mut "123456789".as_bytes(), ChecksumAlgorithm::Crc32c).unwrap(),
+            String::from("e3069283")
+        );
+    }
+}
@@ -0,0 +1,734 @@
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs::Permissions;
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use crate::error::ChiconError;
+use crate::{DirEntry, File, FileSystem, FileType, Metadata, VirtualPath};
+
+/// Compression formats supported by `CompressedFileSystem`, each one is recorded as a suffix of the stored file name
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CompressionCodec {
+    Gzip,
+    Zstd,
+}
+
+const CODECS: [CompressionCodec; 2] = [CompressionCodec::Gzip, CompressionCodec::Zstd];
+
+impl CompressionCodec {
+    pub fn suffix(self) -> &'static str {
+        match self {
+            CompressionCodec::Gzip => "gz",
+            CompressionCodec::Zstd => "zst",
+        }
+    }
+
+    fn compress(self, content: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            CompressionCodec::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(content)?;
+                encoder.finish()
+            }
+            // A single frame recording the content size, read back by `decompressed_len`
+            CompressionCodec::Zstd => {
+                zstd::bulk::compress(content, zstd::DEFAULT_COMPRESSION_LEVEL)
+            }
+        }
+    }
+
+    fn decoder<R: Read>(self, reader: R) -> io::Result<Decoder<R>> {
+        match self {
+            CompressionCodec::Gzip => Ok(Decoder::Gzip(GzDecoder::new(reader))),
+            CompressionCodec::Zstd => Ok(Decoder::Zstd(zstd::Decoder::new(reader)?)),
+        }
+    }
+
+    // Length of the content decompressed from `reader`, taken from the gzip trailer or the zstd frame header
+    // when they record it, otherwise counted by decompressing without keeping the content
+    fn decompressed_len<R: Read + Seek>(self, mut reader: R) -> io::Result<u64> {
+        match self {
+            CompressionCodec::Gzip => {
+                // The trailer records the length modulo 2^32, and only of the last member of the file.
+                // It is exact for the single member written by `sync_all` while the content can't reach 4GB.
+                let compressed_len = reader.seek(SeekFrom::End(0))?;
+                if compressed_len >= GZIP_MIN_LEN
+                    && compressed_len.saturating_mul(DEFLATE_MAX_RATIO) < 1 << 32
+                {
+                    let mut isize = [0u8; 4];
+                    reader.seek(SeekFrom::End(-4))?;
+                    reader.read_exact(&mut isize)?;
+                    return Ok(u64::from(u32::from_le_bytes(isize)));
+                }
+
+                reader.seek(SeekFrom::Start(0))?;
+                io::copy(&mut GzDecoder::new(reader), &mut io::sink())
+            }
+            CompressionCodec::Zstd => {
+                let mut header = Vec::with_capacity(ZSTD_FRAME_HEADER_MAX_LEN);
+                (&mut reader)
+                    .take(ZSTD_FRAME_HEADER_MAX_LEN as u64)
+                    .read_to_end(&mut header)?;
+                // Skippable frames start with another magic number and don't record a size
+                if header.starts_with(&ZSTD_MAGIC_NUMBER) {
+                    if let Ok(Some(len)) = zstd::zstd_safe::get_frame_content_size(&header) {
+                        return Ok(len);
+                    }
+                }
+
+                let mut decoder = zstd::Decoder::new(io::Cursor::new(header).chain(reader))?;
+                io::copy(&mut decoder, &mut io::sink())
+            }
+        }
+    }
+}
+
+const ZSTD_MAGIC_NUMBER: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+const ZSTD_FRAME_HEADER_MAX_LEN: usize = 18;
+// Header and trailer of a gzip member around an empty deflate stream
+const GZIP_MIN_LEN: u64 = 20;
+// Deflate can't expand its input more than this
+const DEFLATE_MAX_RATIO: u64 = 1032;
+
+// Streaming decompression of a stored file
+enum Decoder<R: Read> {
+    Gzip(GzDecoder<R>),
+    Zstd(zstd::Decoder<'static, BufReader<R>>),
+}
+
+impl<R: Read> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Decoder::Gzip(decoder) => decoder.read(buf),
+            Decoder::Zstd(decoder) => decoder.read(buf),
+        }
+    }
+}
+
+fn with_suffix(path: &Path, codec: CompressionCodec) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(".");
+    name.push(codec.suffix());
+    PathBuf::from(name)
+}
+
+/// Choice of the files compressed by a `CompressedFileSystem`
+#[derive(Clone)]
+pub struct CompressionPolicy {
+    codec: CompressionCodec,
+    extensions: Option<Vec<String>>,
+    min_size: u64,
+}
+
+impl Default for CompressionPolicy {
+    fn default() -> Self {
+        CompressionPolicy {
+            codec: CompressionCodec::Zstd,
+            extensions: None,
+            min_size: 0,
+        }
+    }
+}
+
+impl CompressionPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Codec used to compress new files, `Zstd` by default
+    pub fn codec(mut self, codec: CompressionCodec) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    /// Only compress files with one of these extensions, every file by default
+    pub fn extensions<S: AsRef<str>>(mut self, extensions: &[S]) -> Self {
+        self.extensions = Some(
+            extensions
+                .iter()
+                .map(|extension| extension.as_ref().trim_start_matches('.').to_lowercase())
+                .collect(),
+        );
+        self
+    }
+
+    /// Store files smaller than `min_size` bytes without compression, 0 by default
+    pub fn min_size(mut self, min_size: u64) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    fn codec_for(&self, path: &Path, size: u64) -> Option<CompressionCodec> {
+        if size < self.min_size {
+            return None;
+        }
+        if let Some(extensions) = &self.extensions {
+            let extension = path
+                .extension()
+                .map(|extension| extension.to_string_lossy().to_lowercase());
+            if !extension.is_some_and(|extension| extensions.contains(&extension)) {
+                return None;
+            }
+        }
+
+        Some(self.codec)
+    }
+}
+
+/// Structure implementing `FileSystem` trait to compress files stored in the wrapped filesystem.
+///
+/// A compressed file is stored with the suffix of its codec, `myfile.log` becomes `myfile.log.gz` or `myfile.log.zst`,
+/// so that files left uncompressed by the policy, or written without this wrapper, stay readable.
+/// Names ending with a codec suffix are thus reserved. Contents are compressed in memory by `sync_all`, which must be called,
+/// and decompressed while being read, files got from `open_file` are read-only. Seeking backward in a compressed file
+/// decompresses it again from its start.
+/// Sizes given by `metadata` are the decompressed ones, read from the gzip trailer or the zstd frame header,
+/// the files which don't record it are decompressed to count it.
+pub struct CompressedFileSystem<F: FileSystem> {
+    inner: Arc<F>,
+    policy: CompressionPolicy,
+}
+
+impl<F: FileSystem> CompressedFileSystem<F> {
+    pub fn new(inner: F, policy: CompressionPolicy) -> Self {
+        CompressedFileSystem {
+            inner: Arc::new(inner),
+            policy,
+        }
+    }
+}
+
+impl<F> CompressedFileSystem<F>
+where
+    F: FileSystem,
+    ChiconError: From<F::FSError> + From<<F::File as File>::FSError>,
+{
+    // Stored path of `path` and the codec of its content
+    fn resolve(&self, path: &Path) -> (PathBuf, Option<CompressionCodec>) {
+        for codec in CODECS.iter() {
+            let stored_path = with_suffix(path, *codec);
+            if let Ok(metadata) = self.inner.symlink_metadata(&stored_path) {
+                if metadata.file_type == FileType::File {
+                    return (stored_path, Some(*codec));
+                }
+            }
+        }
+
+        (PathBuf::from(path), None)
+    }
+
+    fn decompressed_len(
+        &self,
+        stored_path: &Path,
+        codec: CompressionCodec,
+    ) -> Result<u64, ChiconError> {
+        let file = self.inner.open_file(stored_path)?;
+        Ok(codec.decompressed_len(file)?)
+    }
+
+    fn logical_metadata(
+        &self,
+        stored_path: &Path,
+        codec: Option<CompressionCodec>,
+        mut metadata: Metadata,
+    ) -> Result<Metadata, ChiconError> {
+        if let Some(codec) = codec {
+            metadata.len = self.decompressed_len(stored_path, codec)?;
+            metadata.etag = None;
+        }
+
+        Ok(metadata)
+    }
+}
+
+// Remove the variants of `path` other than `kept`, so that they don't shadow it
+fn remove_other_variants<F: FileSystem>(fs: &F, path: &Path, kept: &Path) -> Result<(), ChiconError>
+where
+    ChiconError: From<F::FSError>,
+{
+    let variants = CODECS
+        .iter()
+        .map(|codec| with_suffix(path, *codec))
+        .chain(std::iter::once(PathBuf::from(path)));
+    for variant in variants {
+        if variant != kept {
+            if let Ok(metadata) = fs.symlink_metadata(&variant) {
+                if metadata.file_type != FileType::Directory {
+                    fs.remove_file(&variant)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+impl<F> FileSystem for CompressedFileSystem<F>
+where
+    F: FileSystem,
+    ChiconError: From<F::FSError>
+        + From<<F::File as File>::FSError>
+        + From<<F::DirEntry as DirEntry>::FSError>,
+{
+    type FSError = ChiconError;
+    type File = CompressedFile<F>;
+    type DirEntry = CompressedDirEntry;
+
+    fn chmod<P: AsRef<Path>>(&self, path: P, perm: Permissions) -> Result<(), Self::FSError> {
+        let (stored_path, _) = self.resolve(&VirtualPath::new(path)?.to_path_buf());
+        Ok(self.inner.chmod(stored_path, perm)?)
+    }
+
+    fn create_file<P: AsRef<Path>>(&self, path: P) -> Result<Self::File, Self::FSError> {
+        Ok(CompressedFile {
+            state: FileState::Writing {
+                fs: Arc::clone(&self.inner),
+                policy: self.policy.clone(),
+                path: VirtualPath::new(path)?.to_path_buf(),
+                content: Vec::new(),
+                completed: false,
+            },
+        })
+    }
+
+    fn create_dir<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
+        Ok(self.inner.create_dir(path)?)
+    }
+
+    fn create_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
+        Ok(self.inner.create_dir_all(path)?)
+    }
+
+    fn open_file<P: AsRef<Path>>(&self, path: P) -> Result<Self::File, Self::FSError> {
+        let (stored_path, codec) = self.resolve(&VirtualPath::new(path)?.to_path_buf());
+        let file = self.inner.open_file(&stored_path)?;
+        let state = match codec {
+            Some(codec) => FileState::Decompressing {
+                fs: Arc::clone(&self.inner),
+                stored_path,
+                codec,
+                decoder: codec.decoder(file)?,
+                position: 0,
+            },
+            None => FileState::Reading(file),
+        };
+
+        Ok(CompressedFile { state })
+    }
+
+    fn read_dir<P: AsRef<Path>>(&self, path: P) -> Result<Vec<Self::DirEntry>, Self::FSError> {
+        let path = VirtualPath::new(path)?.to_path_buf();
+        let mut names = HashSet::new();
+        let mut dir_entries = Vec::new();
+        for entry in self.inner.read_dir(&path)? {
+            let mut name = entry.name()?;
+            let file_type = entry.file_type()?;
+            if file_type == FileType::File {
+                for codec in CODECS.iter() {
+                    let suffix = format!(".{}", codec.suffix());
+                    if name.len() > suffix.len() && name.ends_with(&suffix) {
+                        name.truncate(name.len() - suffix.len());
+                        break;
+                    }
+                }
+            }
+            if names.insert(name.clone()) {
+                dir_entries.push(CompressedDirEntry {
+                    path: path.join(name),
+                    file_type,
+                });
+            }
+        }
+
+        Ok(dir_entries)
+    }
+
+    fn remove_file<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
+        let path = VirtualPath::new(path)?.to_path_buf();
+        let (stored_path, _) = self.resolve(&path);
+        self.inner.remove_file(&stored_path)?;
+        remove_other_variants(self.inner.as_ref(), &path, &stored_path)
+    }
+
+    fn remove_dir<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
+        Ok(self.inner.remove_dir(path)?)
+    }
+
+    fn remove_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
+        Ok(self.inner.remove_dir_all(path)?)
+    }
+
+    fn rename<P: AsRef<Path>>(&self, from: P, to: P) -> Result<(), Self::FSError> {
+        let from = VirtualPath::new(from)?.to_path_buf();
+        let to = VirtualPath::new(to)?.to_path_buf();
+        let (stored_from, codec) = self.resolve(&from);
+        let stored_to = match codec {
+            Some(codec) => with_suffix(&to, codec),
+            None => to.clone(),
+        };
+        self.inner.rename(&stored_from, &stored_to)?;
+        remove_other_variants(self.inner.as_ref(), &to, &stored_to)
+    }
+
+    fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata, Self::FSError> {
+        let (stored_path, codec) = self.resolve(&VirtualPath::new(path)?.to_path_buf());
+        let metadata = self.inner.metadata(&stored_path)?;
+        self.logical_metadata(&stored_path, codec, metadata)
+    }
+
+    fn set_modified<P: AsRef<Path>>(
+        &self,
+        path: P,
+        modified: SystemTime,
+    ) -> Result<(), Self::FSError> {
+        let (stored_path, _) = self.resolve(&VirtualPath::new(path)?.to_path_buf());
+        Ok(self.inner.set_modified(stored_path, modified)?)
+    }
+
+    fn symlink<P: AsRef<Path>>(&self, target: P, link: P) -> Result<(), Self::FSError> {
+        Ok(self.inner.symlink(target, link)?)
+    }
+
+    fn read_link<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Self::FSError> {
+        Ok(self.inner.read_link(path)?)
+    }
+
+    fn symlink_metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata, Self::FSError> {
+        let (stored_path, codec) = self.resolve(&VirtualPath::new(path)?.to_path_buf());
+        let metadata = self.inner.symlink_metadata(&stored_path)?;
+        self.logical_metadata(&stored_path, codec, metadata)
+    }
+
+    fn canonicalize<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Self::FSError> {
+        let (stored_path, codec) = self.resolve(&VirtualPath::new(path)?.to_path_buf());
+        let canonical_path = self.inner.canonicalize(&stored_path)?;
+        match codec {
+            Some(codec) => {
+                let canonical_path = canonical_path.to_string_lossy();
+                let suffix = format!(".{}", codec.suffix());
+                Ok(PathBuf::from(
+                    canonical_path
+                        .strip_suffix(suffix.as_str())
+                        .unwrap_or(&canonical_path),
+                ))
+            }
+            None => Ok(canonical_path),
+        }
+    }
+
+    fn copy<P: AsRef<Path>>(&self, from: P, to: P) -> Result<(), Self::FSError> {
+        let to = VirtualPath::new(to)?.to_path_buf();
+        let (stored_from, codec) = self.resolve(&VirtualPath::new(from)?.to_path_buf());
+        let stored_to = match codec {
+            Some(codec) => with_suffix(&to, codec),
+            None => to.clone(),
+        };
+        self.inner.copy(&stored_from, &stored_to)?;
+        remove_other_variants(self.inner.as_ref(), &to, &stored_to)
+    }
+
+    fn copy_dir_all<P: AsRef<Path>>(&self, from: P, to: P) -> Result<(), Self::FSError> {
+        Ok(self.inner.copy_dir_all(from, to)?)
+    }
+}
+
+enum FileState<F: FileSystem> {
+    Writing {
+        fs: Arc<F>,
+        policy: CompressionPolicy,
+        path: PathBuf,
+        content: Vec<u8>,
+        completed: bool,
+    },
+    // Uncompressed file, read directly
+    Reading(F::File),
+    Decompressing {
+        fs: Arc<F>,
+        stored_path: PathBuf,
+        codec: CompressionCodec,
+        decoder: Decoder<F::File>,
+        position: u64,
+    },
+}
+
+/// Structure implementing `File` trait to represent a file of a `CompressedFileSystem`,
+/// either being written after `create_file` or being read after `open_file`
+pub struct CompressedFile<F: FileSystem> {
+    state: FileState<F>,
+}
+
+impl<F> File for CompressedFile<F>
+where
+    F: FileSystem,
+    ChiconError: From<F::FSError> + From<<F::File as File>::FSError>,
+{
+    type FSError = ChiconError;
+
+    /// Compress the content according to the policy and store it, nothing can be written after
+    fn sync_all(&mut self) -> Result<(), Self::FSError> {
+        let (fs, policy, path, content, completed) = match &mut self.state {
+            FileState::Writing {
+                fs,
+                policy,
+                path,
+                content,
+                completed,
+            } if !*completed => (fs, policy, path, content, completed),
+            _ => return Ok(()),
+        };
+
+        let (stored_path, stored_content) = match policy.codec_for(path, content.len() as u64) {
+            Some(codec) => (with_suffix(path, codec), codec.compress(content)?),
+            None => (path.clone(), std::mem::take(content)),
+        };
+        let mut file = fs.create_file(&stored_path)?;
+        file.write_all(&stored_content)?;
+        file.sync_all()?;
+        remove_other_variants(fs.as_ref(), path, &stored_path)?;
+        *completed = true;
+
+        Ok(())
+    }
+}
+
+impl<F: FileSystem> Read for CompressedFile<F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.state {
+            FileState::Reading(file) => file.read(buf),
+            FileState::Decompressing {
+                decoder, position, ..
+            } => {
+                let nb = decoder.read(buf)?;
+                *position += nb as u64;
+                Ok(nb)
+            }
+            FileState::Writing { .. } => Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "compressed file opened for writing",
+            )),
+        }
+    }
+}
+
+impl<F: FileSystem> Write for CompressedFile<F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.state {
+            FileState::Writing {
+                content,
+                completed: false,
+                ..
+            } => content.write(buf),
+            _ => Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "compressed files can only be written once, after create_file",
+            )),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<F> Seek for CompressedFile<F>
+where
+    F: FileSystem,
+    ChiconError: From<F::FSError>,
+{
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (fs, stored_path, codec, decoder, position) = match &mut self.state {
+            FileState::Reading(file) => return file.seek(pos),
+            FileState::Decompressing {
+                fs,
+                stored_path,
+                codec,
+                decoder,
+                position,
+            } => (fs, stored_path, *codec, decoder, position),
+            FileState::Writing { .. } => {
+                return Err(io::Error::other(
+                    "cannot seek in a compressed file opened for writing",
+                ))
+            }
+        };
+        let open = |fs: &F| {
+            fs.open_file(&*stored_path)
+                .map_err(|err| io::Error::other(ChiconError::from(err).to_string()))
+        };
+
+        let cursor = match pos {
+            SeekFrom::Start(nb) => Some(nb),
+            SeekFrom::End(nb) => codec.decompressed_len(open(fs)?)?.checked_add_signed(nb),
+            SeekFrom::Current(nb) => position.checked_add_signed(nb),
+        }
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Invalid argument: bad cursor value",
+            )
+        })?;
+
+        // The content can only be decompressed forward, going back starts again from the beginning
+        if cursor < *position {
+            *decoder = codec.decoder(open(fs)?)?;
+            *position = 0;
+        }
+        io::copy(
+            &mut decoder.by_ref().take(cursor - *position),
+            &mut io::sink(),
+        )?;
+        *position = cursor;
+
+        Ok(cursor)
+    }
+}
+
+/// Structure implementing `DirEntry` trait to represent an entry of a `CompressedFileSystem`, without the codec suffix
+pub struct CompressedDirEntry {
+    path: PathBuf,
+    file_type: FileType,
+}
+
+impl DirEntry for CompressedDirEntry {
+    type FSError = ChiconError;
+
+    fn path(&self) -> Result<PathBuf, Self::FSError> {
+        Ok(self.path.clone())
+    }
+
+    fn file_type(&self) -> Result<FileType, Self::FSError> {
+        Ok(self.file_type.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemFileSystem;
+    use std::io::Cursor;
+
+    fn write(fs: &CompressedFileSystem<MemFileSystem>, path: &str, content: &[u8]) {
+        let mut file = fs.create_file(path).unwrap();
+        file.write_all(content).unwrap();
+        file.sync_all().unwrap();
+    }
+
+    fn read(fs: &CompressedFileSystem<MemFileSystem>, path: &str) -> Vec<u8> {
+        let mut content = Vec::new();
+        fs.open_file(path)
+            .unwrap()
+            .read_to_end(&mut content)
+            .unwrap();
+        content
+    }
+
+    #[test]
+    fn test_compressed_codecs() {
+        let content = b"coucoutoi ".repeat(1000);
+        for codec in CODECS.iter() {
+            let compressed_fs = CompressedFileSystem::new(
+                MemFileSystem::new(),
+                CompressionPolicy::new().codec(*codec),
+            );
+            compressed_fs.create_dir_all("share/test").unwrap();
+            write(&compressed_fs, "share/test/myfile.log", &content);
+
+            assert_eq!(read(&compressed_fs, "share/test/myfile.log"), content);
+            let mut file = compressed_fs.open_file("share/test/myfile.log").unwrap();
+            let mut buf = [0u8; 4];
+            file.seek(SeekFrom::Start(5000)).unwrap();
+            file.read_exact(&mut buf).unwrap();
+            assert_eq!(&buf, b"couc");
+            file.seek(SeekFrom::Current(-5)).unwrap();
+            file.read_exact(&mut buf).unwrap();
+            assert_eq!(&buf, b" cou");
+            assert_eq!(
+                file.seek(SeekFrom::End(-2)).unwrap(),
+                content.len() as u64 - 2
+            );
+            file.read_exact(&mut buf[..2]).unwrap();
+            assert_eq!(&buf[..2], b"i ");
+            let metadata = compressed_fs.metadata("share/test/myfile.log").unwrap();
+            assert_eq!(metadata.len, content.len() as u64);
+            let dir_entries = compressed_fs.read_dir("share/test").unwrap();
+            assert_eq!(dir_entries.len(), 1);
+            assert_eq!(dir_entries.first().unwrap().name().unwrap(), "myfile.log");
+
+            let stored_path = format!("share/test/myfile.log.{}", codec.suffix());
+            let stored_metadata = compressed_fs.inner.metadata(&stored_path).unwrap();
+            assert!(stored_metadata.len < content.len() as u64 / 10);
+        }
+    }
+
+    #[test]
+    fn test_send() {
+        fn assert_send<T: Send>() {}
+        assert_send::<CompressedFileSystem<crate::OsFileSystem>>();
+        assert_send::<CompressedFile<crate::OsFileSystem>>();
+    }
+
+    #[test]
+    fn test_decompressed_len() {
+        let content = b"coucoutoi ".repeat(1000);
+        for codec in CODECS.iter() {
+            let compressed = codec.compress(&content).unwrap();
+            assert_eq!(
+                codec.decompressed_len(Cursor::new(&compressed)).unwrap(),
+                content.len() as u64
+            );
+        }
+
+        let compressed = CompressionCodec::Zstd.compress(&content).unwrap();
+        assert_eq!(
+            zstd::zstd_safe::get_frame_content_size(&compressed)
+                .ok()
+                .flatten(),
+            Some(content.len() as u64)
+        );
+
+        // Streamed zstd frames don't record their size
+        let compressed = zstd::encode_all(content.as_slice(), 0).unwrap();
+        assert_eq!(
+            CompressionCodec::Zstd
+                .decompressed_len(Cursor::new(&compressed))
+                .unwrap(),
+            content.len() as u64
+        );
+    }
+
+    #[test]
+    fn test_compressed_policy() {
+        let compressed_fs = CompressedFileSystem::new(
+            MemFileSystem::new(),
+            CompressionPolicy::new()
+                .codec(CompressionCodec::Gzip)
+                .extensions(&["log", "json"])
+                .min_size(16),
+        );
+        write(&compressed_fs, "small.log", b"tiny");
+        write(&compressed_fs, "image.png", &[0u8; 64]);
+        write(&compressed_fs, "big.json", &[b'{'; 64]);
+
+        assert!(compressed_fs.inner.metadata("small.log").is_ok());
+        assert!(compressed_fs.inner.metadata("image.png").is_ok());
+        assert!(compressed_fs.inner.metadata("big.json.gz").is_ok());
+        assert_eq!(read(&compressed_fs, "small.log"), b"tiny");
+
+        // Rewriting a compressed file with a small content doesn't leave the compressed one behind
+        write(&compressed_fs, "big.json", b"{}");
+        assert!(compressed_fs.inner.metadata("big.json.gz").is_err());
+        assert_eq!(read(&compressed_fs, "big.json"), b"{}");
+
+        compressed_fs.rename("small.log", "renamed.log").unwrap();
+        assert_eq!(read(&compressed_fs, "renamed.log"), b"tiny");
+        compressed_fs.remove_file("renamed.log").unwrap();
+        assert_eq!(compressed_fs.read_dir("").unwrap().len(), 2);
+    }
+}
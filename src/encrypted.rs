@@ -0,0 +1,741 @@
+use std::convert::TryInto;
+use std::ffi::{OsStr, OsString};
+use std::fs::Permissions;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, KeyInit, Nonce, XChaCha20Poly1305, XNonce};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::error::ChiconError;
+use crate::{DirEntry, File, FileSystem, FileType, Metadata, VirtualPath};
+
+const MAGIC: &[u8; 4] = b"CENC";
+const VERSION: u8 = 1;
+// Magic, version, key identifier and random nonce of the file
+const HEADER_LEN: usize = 4 + 1 + 4 + 24;
+const CHUNK_SIZE: usize = 64 * 1024;
+const TAG_LEN: usize = 16;
+const NAME_NONCE_LEN: usize = 12;
+
+/// Provider of the 256 bits keys used by `EncryptedFileSystem`.
+///
+/// Keys are identified so that they can be rotated: new contents are encrypted with the current key
+/// and the identifier stored in each file gives the key needed to decrypt it.
+pub trait KeyProvider {
+    /// Identifier and value of the key used to encrypt new contents
+    fn current_key(&self) -> Result<(u32, [u8; 32]), ChiconError>;
+    /// Key with the identifier `key_id`, used to decrypt existing contents
+    fn key(&self, key_id: u32) -> Result<[u8; 32], ChiconError>;
+}
+
+/// Structure implementing `KeyProvider` trait with a single key, whose identifier is 0
+pub struct StaticKeyProvider {
+    key: [u8; 32],
+}
+
+impl StaticKeyProvider {
+    pub fn new(key: [u8; 32]) -> Self {
+        StaticKeyProvider { key }
+    }
+}
+
+impl KeyProvider for StaticKeyProvider {
+    fn current_key(&self) -> Result<(u32, [u8; 32]), ChiconError> {
+        Ok((0, self.key))
+    }
+
+    fn key(&self, key_id: u32) -> Result<[u8; 32], ChiconError> {
+        if key_id != 0 {
+            return Err(ChiconError::EncryptionError(format!(
+                "unknown key identifier {}",
+                key_id
+            )));
+        }
+
+        Ok(self.key)
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC accepts any key size");
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+// Nonce of a chunk, the random nonce of the file whose last 8 bytes are xored with the chunk index.
+// 192 bits nonces can be drawn at random for every file without risking a collision under the same key.
+fn chunk_nonce(header: &[u8; HEADER_LEN], index: u64) -> XNonce {
+    let mut nonce = XNonce::clone_from_slice(&header[HEADER_LEN - 24..]);
+    for (byte, index_byte) in nonce[16..].iter_mut().zip(index.to_be_bytes().iter()) {
+        *byte ^= index_byte;
+    }
+
+    nonce
+}
+
+// Associated data of a chunk, binding it to the header and flagging the last chunk against truncation
+fn chunk_aad(header: &[u8; HEADER_LEN], last: bool) -> Vec<u8> {
+    let mut aad = header.to_vec();
+    aad.push(last as u8);
+    aad
+}
+
+// Length of the plain content stored in an encrypted file of `encrypted_len` bytes
+fn plain_len(encrypted_len: u64) -> Result<u64, ChiconError> {
+    let body_len = encrypted_len
+        .checked_sub(HEADER_LEN as u64)
+        .filter(|body_len| *body_len >= TAG_LEN as u64)
+        .ok_or_else(|| ChiconError::EncryptionError(String::from("truncated encrypted file")))?;
+    let chunks = body_len.div_ceil((CHUNK_SIZE + TAG_LEN) as u64);
+
+    Ok(body_len - chunks * TAG_LEN as u64)
+}
+
+/// Structure implementing `FileSystem` trait to encrypt the contents, and optionally the names, of the files stored in the wrapped filesystem.
+///
+/// Contents are split in chunks of 64KB encrypted with XChaCha20-Poly1305, so that any modification or truncation is detected
+/// and reads can seek without decrypting the whole file. Contents are written once, by a file got from `create_file`
+/// which must be completed with `sync_all`, files got from `open_file` are read-only.
+///
+/// Names are encrypted deterministically, component by component, so that paths can still be looked up:
+/// two identical names give the same encrypted name. Encrypted names are hex encoded, which limits plain names to about 100 bytes.
+pub struct EncryptedFileSystem<F: FileSystem, K: KeyProvider> {
+    inner: F,
+    key_provider: K,
+    name_keys: Option<([u8; 32], ChaCha20Poly1305)>,
+}
+
+impl<F: FileSystem, K: KeyProvider> EncryptedFileSystem<F, K> {
+    pub fn new(inner: F, key_provider: K) -> Self {
+        EncryptedFileSystem {
+            inner,
+            key_provider,
+            name_keys: None,
+        }
+    }
+
+    /// Encrypt file and directory names with keys derived from `name_key`, which must never change for a given filesystem
+    pub fn encrypt_names(mut self, name_key: [u8; 32]) -> Self {
+        let nonce_key = hmac_sha256(&name_key, b"chicon name nonce");
+        let cipher_key = hmac_sha256(&name_key, b"chicon name cipher");
+        self.name_keys = Some((nonce_key, ChaCha20Poly1305::new(&cipher_key.into())));
+        self
+    }
+
+    /// Get back the wrapped filesystem
+    pub fn into_inner(self) -> F {
+        self.inner
+    }
+
+    fn encrypt_name(&self, name: &OsStr) -> Result<OsString, ChiconError> {
+        let (nonce_key, cipher) = match &self.name_keys {
+            Some(name_keys) => name_keys,
+            None => return Ok(name.to_os_string()),
+        };
+        let nonce = &hmac_sha256(nonce_key, name.as_bytes())[..NAME_NONCE_LEN];
+        let encrypted = cipher
+            .encrypt(Nonce::from_slice(nonce), name.as_bytes())
+            .map_err(|_| {
+                ChiconError::EncryptionError(format!(
+                    "cannot encrypt name {}",
+                    name.to_string_lossy()
+                ))
+            })?;
+
+        Ok(OsString::from(hex::encode([nonce, &encrypted].concat())))
+    }
+
+    fn decrypt_name(&self, name: &OsStr) -> Result<OsString, ChiconError> {
+        let (_, cipher) = match &self.name_keys {
+            Some(name_keys) => name_keys,
+            None => return Ok(name.to_os_string()),
+        };
+        let err = || {
+            ChiconError::EncryptionError(format!("cannot decrypt name {}", name.to_string_lossy()))
+        };
+        let encrypted = hex::decode(name.as_bytes()).map_err(|_| err())?;
+        if encrypted.len() < NAME_NONCE_LEN + TAG_LEN {
+            return Err(err());
+        }
+        let (nonce, encrypted) = encrypted.split_at(NAME_NONCE_LEN);
+        let decrypted = cipher
+            .decrypt(Nonce::from_slice(nonce), encrypted)
+            .map_err(|_| err())?;
+
+        Ok(OsString::from_vec(decrypted))
+    }
+
+    // Encrypt the names of a path, `.`, `..` and the root are kept as is
+    fn encrypt_path(&self, path: &Path) -> Result<PathBuf, ChiconError> {
+        let mut encrypted = PathBuf::new();
+        for component in path.components() {
+            match component {
+                Component::Normal(name) => encrypted.push(self.encrypt_name(name)?),
+                component => encrypted.push(component.as_os_str()),
+            }
+        }
+
+        Ok(encrypted)
+    }
+
+    // Decrypt the names of a path, names which aren't encrypted, like the host part of a canonical path, are kept as is
+    fn decrypt_path(&self, path: &Path, lossy: bool) -> Result<PathBuf, ChiconError> {
+        let mut decrypted = PathBuf::new();
+        for component in path.components() {
+            match component {
+                Component::Normal(name) => match self.decrypt_name(name) {
+                    Ok(name) => decrypted.push(name),
+                    Err(_) if lossy => decrypted.push(name),
+                    Err(err) => return Err(err),
+                },
+                component => decrypted.push(component.as_os_str()),
+            }
+        }
+
+        Ok(decrypted)
+    }
+
+    fn inner_path<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, ChiconError> {
+        self.encrypt_path(&VirtualPath::new(path)?.to_path_buf())
+    }
+
+    fn plain_metadata(&self, mut metadata: Metadata) -> Result<Metadata, ChiconError> {
+        if metadata.file_type == FileType::File {
+            metadata.len = plain_len(metadata.len)?;
+        }
+
+        Ok(metadata)
+    }
+}
+
+impl<F, K> FileSystem for EncryptedFileSystem<F, K>
+where
+    F: FileSystem,
+    K: KeyProvider,
+    ChiconError: From<F::FSError>
+        + From<<F::File as File>::FSError>
+        + From<<F::DirEntry as DirEntry>::FSError>,
+{
+    type FSError = ChiconError;
+    type File = EncryptedFile<F::File>;
+    type DirEntry = EncryptedDirEntry;
+
+    fn chmod<P: AsRef<Path>>(&self, path: P, perm: Permissions) -> Result<(), Self::FSError> {
+        Ok(self.inner.chmod(self.inner_path(path)?, perm)?)
+    }
+
+    fn create_file<P: AsRef<Path>>(&self, path: P) -> Result<Self::File, Self::FSError> {
+        let (key_id, key) = self.key_provider.current_key()?;
+        let mut header = [0u8; HEADER_LEN];
+        header[..4].copy_from_slice(MAGIC);
+        header[4] = VERSION;
+        header[5..9].copy_from_slice(&key_id.to_be_bytes());
+        OsRng.fill_bytes(&mut header[9..]);
+
+        let mut inner = self.inner.create_file(self.inner_path(path)?)?;
+        inner.write_all(&header)?;
+
+        Ok(EncryptedFile {
+            inner,
+            cipher: XChaCha20Poly1305::new(&key.into()),
+            header,
+            state: FileState::Writing {
+                pending: Vec::new(),
+                index: 0,
+                completed: false,
+            },
+        })
+    }
+
+    fn create_dir<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
+        Ok(self.inner.create_dir(self.inner_path(path)?)?)
+    }
+
+    fn create_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
+        Ok(self.inner.create_dir_all(self.inner_path(path)?)?)
+    }
+
+    fn open_file<P: AsRef<Path>>(&self, path: P) -> Result<Self::File, Self::FSError> {
+        let mut inner = self.inner.open_file(self.inner_path(path)?)?;
+        let mut header = [0u8; HEADER_LEN];
+        inner.read_exact(&mut header)?;
+        if &header[..4] != MAGIC || header[4] != VERSION {
+            return Err(ChiconError::EncryptionError(String::from(
+                "not an encrypted file",
+            )));
+        }
+        let key_id = u32::from_be_bytes(header[5..9].try_into().unwrap());
+        let key = self.key_provider.key(key_id)?;
+        let len = plain_len(inner.seek(SeekFrom::End(0))?)?;
+
+        let mut file = EncryptedFile {
+            inner,
+            cipher: XChaCha20Poly1305::new(&key.into()),
+            header,
+            state: FileState::Reading {
+                len,
+                position: 0,
+                chunk: None,
+            },
+        };
+        // Authenticate the last chunk right away, even an empty one, so that a truncated or emptied file
+        // is rejected before reading reaches the end of it
+        let last_index = std::cmp::max(1, len.div_ceil(CHUNK_SIZE as u64)) - 1;
+        let last_chunk = file.read_chunk(last_index, len)?;
+        if let FileState::Reading { chunk, .. } = &mut file.state {
+            *chunk = Some((last_index, last_chunk));
+        }
+
+        Ok(file)
+    }
+
+    fn read_dir<P: AsRef<Path>>(&self, path: P) -> Result<Vec<Self::DirEntry>, Self::FSError> {
+        let path = VirtualPath::new(path)?.to_path_buf();
+        let mut dir_entries = Vec::new();
+        for entry in self.inner.read_dir(self.encrypt_path(&path)?)? {
+            dir_entries.push(EncryptedDirEntry {
+                path: path.join(self.decrypt_name(OsStr::new(&entry.name()?))?),
+                file_type: entry.file_type()?,
+            });
+        }
+
+        Ok(dir_entries)
+    }
+
+    fn remove_file<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
+        Ok(self.inner.remove_file(self.inner_path(path)?)?)
+    }
+
+    fn remove_dir<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
+        Ok(self.inner.remove_dir(self.inner_path(path)?)?)
+    }
+
+    fn remove_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
+        Ok(self.inner.remove_dir_all(self.inner_path(path)?)?)
+    }
+
+    fn rename<P: AsRef<Path>>(&self, from: P, to: P) -> Result<(), Self::FSError> {
+        Ok(self
+            .inner
+            .rename(self.inner_path(from)?, self.inner_path(to)?)?)
+    }
+
+    fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata, Self::FSError> {
+        self.plain_metadata(self.inner.metadata(self.inner_path(path)?)?)
+    }
+
+    fn symlink<P: AsRef<Path>>(&self, target: P, link: P) -> Result<(), Self::FSError> {
+        Ok(self
+            .inner
+            .symlink(self.encrypt_path(target.as_ref())?, self.inner_path(link)?)?)
+    }
+
+    fn read_link<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Self::FSError> {
+        let target = self.inner.read_link(self.inner_path(path)?)?;
+        self.decrypt_path(&target, false)
+    }
+
+    fn symlink_metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata, Self::FSError> {
+        self.plain_metadata(self.inner.symlink_metadata(self.inner_path(path)?)?)
+    }
+
+    fn canonicalize<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Self::FSError> {
+        let canonical_path = self.inner.canonicalize(self.inner_path(path)?)?;
+        self.decrypt_path(&canonical_path, true)
+    }
+
+    /// Encrypted contents are copied as is, they don't depend on their path
+    fn set_modified<P: AsRef<Path>>(
+        &self,
+        path: P,
+        modified: SystemTime,
+    ) -> Result<(), Self::FSError> {
+        Ok(self.inner.set_modified(self.inner_path(path)?, modified)?)
+    }
+
+    fn copy<P: AsRef<Path>>(&self, from: P, to: P) -> Result<(), Self::FSError> {
+        Ok(self
+            .inner
+            .copy(self.inner_path(from)?, self.inner_path(to)?)?)
+    }
+
+    fn copy_dir_all<P: AsRef<Path>>(&self, from: P, to: P) -> Result<(), Self::FSError> {
+        Ok(self
+            .inner
+            .copy_dir_all(self.inner_path(from)?, self.inner_path(to)?)?)
+    }
+}
+
+enum FileState {
+    Writing {
+        // Plain content not encrypted yet, the last chunk is only encrypted by `sync_all`
+        pending: Vec<u8>,
+        index: u64,
+        completed: bool,
+    },
+    Reading {
+        len: u64,
+        position: u64,
+        chunk: Option<(u64, Vec<u8>)>,
+    },
+}
+
+/// Structure implementing `File` trait to represent a file of an `EncryptedFileSystem`,
+/// either being written after `create_file` or being read after `open_file`
+pub struct EncryptedFile<T: File> {
+    inner: T,
+    cipher: XChaCha20Poly1305,
+    header: [u8; HEADER_LEN],
+    state: FileState,
+}
+
+impl<T: File> EncryptedFile<T> {
+    fn write_chunk(&mut self, plain: &[u8], index: u64, last: bool) -> io::Result<()> {
+        let nonce = chunk_nonce(&self.header, index);
+        let aad = chunk_aad(&self.header, last);
+        let encrypted = self
+            .cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: plain,
+                    aad: &aad,
+                },
+            )
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "cannot encrypt chunk"))?;
+
+        self.inner.write_all(&encrypted)
+    }
+
+    fn read_chunk(&mut self, index: u64, len: u64) -> io::Result<Vec<u8>> {
+        let chunks = std::cmp::max(1, len.div_ceil(CHUNK_SIZE as u64));
+        let last = index + 1 == chunks;
+        let plain_len = if last {
+            len - index * CHUNK_SIZE as u64
+        } else {
+            CHUNK_SIZE as u64
+        };
+        let offset = HEADER_LEN as u64 + index * (CHUNK_SIZE + TAG_LEN) as u64;
+        let mut encrypted = vec![0u8; plain_len as usize + TAG_LEN];
+        self.inner.seek(SeekFrom::Start(offset))?;
+        self.inner.read_exact(&mut encrypted)?;
+
+        let nonce = chunk_nonce(&self.header, index);
+        let aad = chunk_aad(&self.header, last);
+        self.cipher
+            .decrypt(
+                &nonce,
+                Payload {
+                    msg: &encrypted,
+                    aad: &aad,
+                },
+            )
+            .map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "encrypted chunk has been modified or truncated",
+                )
+            })
+    }
+}
+
+impl<T> File for EncryptedFile<T>
+where
+    T: File,
+    ChiconError: From<T::FSError>,
+{
+    type FSError = ChiconError;
+
+    /// Encrypt the last chunk and sync the wrapped file, nothing can be written after
+    fn sync_all(&mut self) -> Result<(), Self::FSError> {
+        let (pending, index) = match &mut self.state {
+            FileState::Writing {
+                pending,
+                index,
+                completed,
+            } if !*completed => {
+                *completed = true;
+                (std::mem::take(pending), *index)
+            }
+            _ => return Ok(()),
+        };
+        self.write_chunk(&pending, index, true)?;
+
+        Ok(self.inner.sync_all()?)
+    }
+}
+
+impl<T: File> Read for EncryptedFile<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let (len, position, cached_index) = match &self.state {
+            FileState::Reading {
+                len,
+                position,
+                chunk,
+            } => (*len, *position, chunk.as_ref().map(|(index, _)| *index)),
+            FileState::Writing { .. } => {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "encrypted file opened for writing",
+                ))
+            }
+        };
+        if position >= len || buf.is_empty() {
+            return Ok(0);
+        }
+
+        let index = position / CHUNK_SIZE as u64;
+        let loaded = if cached_index != Some(index) {
+            Some(self.read_chunk(index, len)?)
+        } else {
+            None
+        };
+        if let FileState::Reading {
+            position, chunk, ..
+        } = &mut self.state
+        {
+            if let Some(loaded) = loaded {
+                *chunk = Some((index, loaded));
+            }
+            let plain = &chunk.as_ref().unwrap().1;
+            let offset = (*position % CHUNK_SIZE as u64) as usize;
+            let nb = std::cmp::min(buf.len(), plain.len() - offset);
+            buf[..nb].copy_from_slice(&plain[offset..offset + nb]);
+            *position += nb as u64;
+            return Ok(nb);
+        }
+
+        Ok(0)
+    }
+}
+
+impl<T: File> Write for EncryptedFile<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let full_chunks = match &mut self.state {
+            FileState::Writing {
+                pending,
+                index,
+                completed: false,
+            } => {
+                pending.extend_from_slice(buf);
+                let mut full_chunks = Vec::new();
+                // Keep at least one byte pending so that the last chunk is never written here
+                while pending.len() > CHUNK_SIZE {
+                    let rest = pending.split_off(CHUNK_SIZE);
+                    full_chunks.push((*index, std::mem::replace(pending, rest)));
+                    *index += 1;
+                }
+                full_chunks
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "encrypted files can only be written once, after create_file",
+                ))
+            }
+        };
+        for (index, plain) in full_chunks {
+            self.write_chunk(&plain, index, false)?;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<T: File> Seek for EncryptedFile<T> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (len, position) = match &mut self.state {
+            FileState::Reading { len, position, .. } => (*len, position),
+            FileState::Writing { .. } => {
+                return Err(io::Error::other(
+                    "cannot seek in an encrypted file opened for writing",
+                ))
+            }
+        };
+        let cursor = match pos {
+            SeekFrom::Start(nb) => Some(nb),
+            SeekFrom::End(nb) => (len as i64)
+                .checked_add(nb)
+                .filter(|c| *c >= 0)
+                .map(|c| c as u64),
+            SeekFrom::Current(nb) => (*position as i64)
+                .checked_add(nb)
+                .filter(|c| *c >= 0)
+                .map(|c| c as u64),
+        }
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Invalid argument: bad cursor value",
+            )
+        })?;
+        *position = cursor;
+
+        Ok(cursor)
+    }
+}
+
+/// Structure implementing `DirEntry` trait to represent an entry of an `EncryptedFileSystem`, with its decrypted name
+pub struct EncryptedDirEntry {
+    path: PathBuf,
+    file_type: FileType,
+}
+
+impl DirEntry for EncryptedDirEntry {
+    type FSError = ChiconError;
+
+    fn path(&self) -> Result<PathBuf, Self::FSError> {
+        Ok(self.path.clone())
+    }
+
+    fn file_type(&self) -> Result<FileType, Self::FSError> {
+        Ok(self.file_type.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemFileSystem;
+
+    const KEY: [u8; 32] = [7u8; 32];
+
+    #[test]
+    fn test_encrypted_content() {
+        let encrypted_fs =
+            EncryptedFileSystem::new(MemFileSystem::new(), StaticKeyProvider::new(KEY));
+        encrypted_fs.create_dir_all("share/test").unwrap();
+        let content: Vec<u8> = (0..(CHUNK_SIZE * 2 + 10))
+            .map(|i| (i % 251) as u8)
+            .collect();
+        {
+            let mut file = encrypted_fs.create_file("share/test/myfile").unwrap();
+            file.write_all(&content).unwrap();
+            file.sync_all().unwrap();
+            assert!(file.write_all(b"more").is_err());
+        }
+
+        let mut file = encrypted_fs.open_file("share/test/myfile").unwrap();
+        let mut decrypted = Vec::new();
+        file.read_to_end(&mut decrypted).unwrap();
+        assert_eq!(decrypted, content);
+        file.seek(SeekFrom::Start(CHUNK_SIZE as u64 + 3)).unwrap();
+        let mut buf = [0u8; 4];
+        file.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf[..], &content[CHUNK_SIZE + 3..CHUNK_SIZE + 7]);
+        assert_eq!(
+            encrypted_fs.metadata("share/test/myfile").unwrap().len,
+            content.len() as u64
+        );
+
+        let mem_fs = encrypted_fs.into_inner();
+        let mut raw = Vec::new();
+        mem_fs
+            .open_file("share/test/myfile")
+            .unwrap()
+            .read_to_end(&mut raw)
+            .unwrap();
+        assert!(raw.windows(16).all(|window| window != &content[..16]));
+    }
+
+    #[test]
+    fn test_encrypted_tampering() {
+        let encrypted_fs =
+            EncryptedFileSystem::new(MemFileSystem::new(), StaticKeyProvider::new(KEY));
+        {
+            let mut file = encrypted_fs.create_file("myfile").unwrap();
+            file.write_all(b"coucoutoi").unwrap();
+            file.sync_all().unwrap();
+        }
+        let mem_fs = encrypted_fs.into_inner();
+        let mut raw = Vec::new();
+        mem_fs
+            .open_file("myfile")
+            .unwrap()
+            .read_to_end(&mut raw)
+            .unwrap();
+        raw[HEADER_LEN] ^= 1;
+        mem_fs
+            .create_file("myfile")
+            .unwrap()
+            .write_all(&raw)
+            .unwrap();
+
+        let encrypted_fs = EncryptedFileSystem::new(mem_fs, StaticKeyProvider::new(KEY));
+        assert!(encrypted_fs.open_file("myfile").is_err());
+        let wrong_key_fs =
+            EncryptedFileSystem::new(encrypted_fs.into_inner(), StaticKeyProvider::new([1u8; 32]));
+        assert!(wrong_key_fs.open_file("myfile").is_err());
+
+        // Keeping the header and replacing the body with a bare tag must not give an empty file
+        let mem_fs = wrong_key_fs.into_inner();
+        raw.truncate(HEADER_LEN);
+        raw.extend_from_slice(&[0u8; TAG_LEN]);
+        mem_fs
+            .create_file("myfile")
+            .unwrap()
+            .write_all(&raw)
+            .unwrap();
+        let encrypted_fs = EncryptedFileSystem::new(mem_fs, StaticKeyProvider::new(KEY));
+        assert!(encrypted_fs.open_file("myfile").is_err());
+    }
+
+    #[test]
+    fn test_encrypted_empty_file() {
+        let encrypted_fs =
+            EncryptedFileSystem::new(MemFileSystem::new(), StaticKeyProvider::new(KEY));
+        encrypted_fs
+            .create_file("myfile")
+            .unwrap()
+            .sync_all()
+            .unwrap();
+
+        let mut content = Vec::new();
+        encrypted_fs
+            .open_file("myfile")
+            .unwrap()
+            .read_to_end(&mut content)
+            .unwrap();
+        assert!(content.is_empty());
+    }
+
+    #[test]
+    fn test_encrypted_names() {
+        let encrypted_fs =
+            EncryptedFileSystem::new(MemFileSystem::new(), StaticKeyProvider::new(KEY))
+                .encrypt_names([3u8; 32]);
+        encrypted_fs.create_dir_all("share/test").unwrap();
+        {
+            let mut file = encrypted_fs.create_file("share/test/myfile").unwrap();
+            file.write_all(b"coucoutoi").unwrap();
+            file.sync_all().unwrap();
+        }
+
+        let dir_entries = encrypted_fs.read_dir("share/test").unwrap();
+        assert_eq!(dir_entries.len(), 1);
+        assert_eq!(dir_entries.first().unwrap().name().unwrap(), "myfile");
+        let mut content = String::new();
+        encrypted_fs
+            .open_file("share/test/myfile")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(String::from("coucoutoi"), content);
+
+        let mem_fs = encrypted_fs.into_inner();
+        assert!(mem_fs.metadata("share").is_err());
+        let dir_entries = mem_fs.read_dir("").unwrap();
+        assert_eq!(dir_entries.len(), 1);
+        assert_ne!(dir_entries.first().unwrap().name().unwrap(), "share");
+    }
+}
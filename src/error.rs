@@ -3,7 +3,9 @@ use std::path::PathBuf;
 
 use rusoto_core::RusotoError;
 use rusoto_s3::{
-    CopyObjectError, DeleteObjectError, DeleteObjectsError, GetObjectError, ListObjectsV2Error, PutObjectError,
+    AbortMultipartUploadError, CompleteMultipartUploadError, CopyObjectError,
+    CreateMultipartUploadError, DeleteObjectError, DeleteObjectsError, GetObjectError,
+    HeadObjectError, ListObjectsV2Error, PutObjectError, UploadPartCopyError,
 };
 
 
@@ -28,22 +30,46 @@ pub enum ChiconError {
     RelativePath,
     #[fail(display = "path is incorrect or do not exist")]
     BadPath,
+    #[fail(display = "path goes above the root of the filesystem: {:?}", _0)]
+    PathOutsideRoot(PathBuf),
+    #[fail(display = "operation not supported by this filesystem: {}", _0)]
+    Unsupported(String),
+    #[fail(display = "no filesystem is mounted at path: {:?}", _0)]
+    NotMounted(PathBuf),
+    #[fail(display = "rename across mount points can't be atomic: {:?} -> {:?}", _0, _1)]
+    CrossMountRename(PathBuf, PathBuf),
+    #[fail(display = "encryption error: {}", _0)]
+    EncryptionError(String),
     #[fail(display = "Rusoto GetObjectError error: {:?}", _0)]
-    RusotoGetObjectError(RusotoError<GetObjectError>),
+    RusotoGetObjectError(Box<RusotoError<GetObjectError>>),
     #[fail(display = "Rusoto PutObjectError error: {:?}", _0)]
-    RusotoPutObjectError(RusotoError<PutObjectError>),
+    RusotoPutObjectError(Box<RusotoError<PutObjectError>>),
     #[fail(display = "Rusoto DeleteObjectError error: {:?}", _0)]
-    RusotoDeleteObjectError(RusotoError<DeleteObjectError>),
+    RusotoDeleteObjectError(Box<RusotoError<DeleteObjectError>>),
     #[fail(display = "Rusoto DeleteObjectsError error: {:?}", _0)]
-    RusotoDeleteObjectsError(RusotoError<DeleteObjectsError>),
+    RusotoDeleteObjectsError(Box<RusotoError<DeleteObjectsError>>),
     #[fail(display = "Rusoto CopyObjectError error: {:?}", _0)]
-    RusotoCopyObjectError(RusotoError<CopyObjectError>),
+    RusotoCopyObjectError(Box<RusotoError<CopyObjectError>>),
     #[fail(display = "Rusoto ListObjectsV2Error error: {:?}", _0)]
-    RusotoListObjectsV2Error(RusotoError<ListObjectsV2Error>),
+    RusotoListObjectsV2Error(Box<RusotoError<ListObjectsV2Error>>),
+    #[fail(display = "Rusoto HeadObjectError error: {:?}", _0)]
+    RusotoHeadObjectError(Box<RusotoError<HeadObjectError>>),
+    #[fail(display = "Rusoto CreateMultipartUploadError error: {:?}", _0)]
+    RusotoCreateMultipartUploadError(Box<RusotoError<CreateMultipartUploadError>>),
+    #[fail(display = "Rusoto UploadPartCopyError error: {:?}", _0)]
+    RusotoUploadPartCopyError(Box<RusotoError<UploadPartCopyError>>),
+    #[fail(display = "Rusoto CompleteMultipartUploadError error: {:?}", _0)]
+    RusotoCompleteMultipartUploadError(Box<RusotoError<CompleteMultipartUploadError>>),
+    #[fail(display = "Rusoto AbortMultipartUploadError error: {:?}", _0)]
+    RusotoAbortMultipartUploadError(Box<RusotoError<AbortMultipartUploadError>>),
     #[fail(display = "SSH error: {:?}", _0)]
     SSHError(ssh2::Error),
     #[fail(display = "SSH execution error: {:?}", _0)]
     SSHExecutionError(String),
+    #[fail(display = "host key of {} doesn't match the known one", _0)]
+    HostKeyMismatch(String),
+    #[fail(display = "host key of {} is unknown", _0)]
+    UnknownHostKey(String),
     #[fail(display = "SFTP error")]
     SFTPError,
     #[fail(display = "Openstack error: {:?}", _0)]
@@ -58,6 +84,8 @@ pub enum ChiconError {
     MemDirNotFound(PathBuf),
     #[fail(display = "Error memory directory is not empty: {:?}", _0)]
     MemDirNotEmpty(PathBuf),
+    #[fail(display = "Invalid glob pattern: {:?}", _0)]
+    PatternError(glob::PatternError),
 }
 
 from_error!(std::io::Error, ChiconError, ChiconError::IOError);
@@ -92,6 +120,32 @@ from_error!(
     ChiconError,
     ChiconError::RusotoListObjectsV2Error
 );
+from_error!(
+    RusotoError<HeadObjectError>,
+    ChiconError,
+    ChiconError::RusotoHeadObjectError
+);
+from_error!(
+    RusotoError<CreateMultipartUploadError>,
+    ChiconError,
+    ChiconError::RusotoCreateMultipartUploadError
+);
+from_error!(
+    RusotoError<UploadPartCopyError>,
+    ChiconError,
+    ChiconError::RusotoUploadPartCopyError
+);
+from_error!(
+    RusotoError<CompleteMultipartUploadError>,
+    ChiconError,
+    ChiconError::RusotoCompleteMultipartUploadError
+);
+from_error!(
+    RusotoError<AbortMultipartUploadError>,
+    ChiconError,
+    ChiconError::RusotoAbortMultipartUploadError
+);
 from_error!(osauth::Error, ChiconError, ChiconError::OpenstackError);
 from_error!(BorrowError, ChiconError, ChiconError::BorrowError);
 from_error!(BorrowMutError, ChiconError, ChiconError::BorrowMutError);
+from_error!(glob::PatternError, ChiconError, ChiconError::PatternError);
@@ -132,32 +132,65 @@ extern crate serde;
 extern crate tokio;
 #[macro_use]
 extern crate failure;
+extern crate base64;
+extern crate crc32c;
+extern crate glob;
+extern crate hex;
+extern crate md5;
+extern crate sha2;
 
+mod cached;
+mod checksum;
+mod compressed;
+mod encrypted;
 mod error;
 mod mem;
+mod mount;
 mod os;
+mod overlay;
+mod read_only;
 mod s3;
+mod session;
 mod sftp;
 mod ssh;
+mod sub;
 // mod swift;
+mod sync;
+mod transfer;
+mod virtual_path;
 
 use std::fs::Permissions;
 use std::io::{Read, Seek, Write};
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
+pub use cached::*;
+pub use checksum::ChecksumAlgorithm;
+pub use compressed::*;
+pub use encrypted::*;
 pub use error::ChiconError;
 pub use mem::*;
+pub use mount::{MountDirEntry, MountFile, MountFileSystem};
 pub use os::*;
+pub use overlay::*;
+pub use read_only::*;
 pub use s3::{S3DirEntry, S3File, S3FileSystem};
+pub use session::{HostKeyCheck, JumpHost, SSHAuth};
 pub use sftp::*;
 pub use ssh::*;
+pub use sub::*;
+pub use sync::*;
+pub use transfer::*;
+pub use virtual_path::VirtualPath;
 
 ///
 /// The FileSystem trait needs to be implemented if you want a fully available abstract filesystem.
 /// For now we have few implementations as OSFileSystem, S3FileSystem, SFTPFileSystem, SSHFileSystem, MemFileSystem
 ///
 pub trait FileSystem {
-    type FSError;
+    type FSError: From<std::io::Error>
+        + From<<Self::File as File>::FSError>
+        + From<<Self::DirEntry as DirEntry>::FSError>;
     type File: File;
     type DirEntry: DirEntry;
 
@@ -171,6 +204,114 @@ pub trait FileSystem {
     fn remove_dir<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError>;
     fn remove_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError>;
     fn rename<P: AsRef<Path>>(&self, from: P, to: P) -> Result<(), Self::FSError>;
+    fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata, Self::FSError>;
+    /// Create a symbolic link at `link` pointing to `target`
+    fn symlink<P: AsRef<Path>>(&self, target: P, link: P) -> Result<(), Self::FSError>;
+    /// Read the target of the symbolic link `path`
+    fn read_link<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Self::FSError>;
+    /// Like `metadata` but without following symbolic links
+    fn symlink_metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata, Self::FSError>;
+    /// Absolute form of `path` with `.`, `..` and symbolic links resolved.
+    /// Paths of S3 and in-memory filesystems are relative to the root of the bucket or of the filesystem.
+    fn canonicalize<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Self::FSError>;
+
+    /// Copy the file `from` to `to`, overwriting `to` if it already exists.
+    ///
+    /// The default implementation streams the content through `open_file` and `create_file`,
+    /// backends able to copy server-side override it.
+    fn copy<P: AsRef<Path>>(&self, from: P, to: P) -> Result<(), Self::FSError> {
+        let mut src = self.open_file(from)?;
+        let mut dst = self.create_file(to)?;
+        std::io::copy(&mut src, &mut dst)?;
+        dst.sync_all()?;
+
+        Ok(())
+    }
+
+    /// Compute the checksum of a file as a lowercase hexadecimal string.
+    ///
+    /// The default implementation hashes the content read through `open_file`,
+    /// backends able to get it without downloading the file override it.
+    fn checksum<P: AsRef<Path>>(
+        &self,
+        path: P,
+        algorithm: ChecksumAlgorithm,
+    ) -> Result<String, Self::FSError> {
+        let mut file = self.open_file(path)?;
+        checksum::hash_reader(&mut file, algorithm).map_err(Self::FSError::from)
+    }
+
+    /// Set the modification time of `path`.
+    ///
+    /// The default implementation fails with an `Unsupported` IO error, backends able to set it override it.
+    fn set_modified<P: AsRef<Path>>(&self, _path: P, _modified: SystemTime) -> Result<(), Self::FSError> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "setting the modification time isn't supported by this filesystem",
+        )
+        .into())
+    }
+
+    /// Write the file `path` atomically, readers either see the previous content or the whole new one.
+    ///
+    /// `write` fills a hidden temporary file created next to `path`, which is synced and renamed to `path` on success.
+    /// The temporary file is removed if `write`, the sync or the rename fails.
+    /// It relies on `rename` replacing `path` atomically: SFTP servers without the `posix-rename@openssh.com`
+    /// extension can't, an existing `path` is moved aside for a moment and readers may then find no file.
+    fn atomic_write<P, F>(&self, path: P, write: F) -> Result<(), Self::FSError>
+    where
+        P: AsRef<Path>,
+        F: FnOnce(&mut Self::File) -> Result<(), Self::FSError>,
+    {
+        let path = path.as_ref();
+        let tmp_path = temporary_path(path);
+
+        let result = self
+            .create_file(&tmp_path)
+            .and_then(|mut file| {
+                write(&mut file)?;
+                file.sync_all()?;
+                Ok(())
+            })
+            .and_then(|_| self.rename(tmp_path.as_path(), path));
+        if result.is_err() {
+            let _ = self.remove_file(&tmp_path);
+        }
+
+        result
+    }
+
+    /// Recursively copy the directory `from` and all its entries to `to`, symbolic links are copied as links.
+    fn copy_dir_all<P: AsRef<Path>>(&self, from: P, to: P) -> Result<(), Self::FSError> {
+        let from = from.as_ref();
+        let to = to.as_ref();
+        self.create_dir_all(to)?;
+
+        for entry in self.read_dir(from)? {
+            let name = entry.name()?;
+            match entry.file_type()? {
+                FileType::Directory => self.copy_dir_all(from.join(&name), to.join(&name))?,
+                FileType::File => self.copy(from.join(&name), to.join(&name))?,
+                FileType::Symlink => self.symlink(self.read_link(from.join(&name))?, to.join(&name))?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Hidden sibling of `path` used as temporary file by `FileSystem::atomic_write` and SFTP renames
+pub(crate) fn temporary_path(path: &Path) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|file_name| file_name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let nanos = SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.subsec_nanos())
+        .unwrap_or_default();
+
+    path.with_file_name(format!(".{}.{}-{}.tmp", file_name, std::process::id(), nanos))
 }
 
 /// Trait that represent a file inside our FileSystem. Associated type `File` in our `FileSystem` trait must implement this trait.
@@ -203,3 +344,24 @@ pub enum FileType {
     File,
     Symlink,
 }
+
+/// Metadata information about a file or a directory, fields are `None` when the backend can't provide them
+#[derive(Clone, Debug)]
+pub struct Metadata {
+    pub file_type: FileType,
+    pub len: u64,
+    pub modified: Option<SystemTime>,
+    pub permissions: Option<Permissions>,
+    /// Entity tag of the object, only provided by S3
+    pub etag: Option<String>,
+}
+
+impl Metadata {
+    pub fn is_dir(&self) -> bool {
+        self.file_type == FileType::Directory
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.file_type == FileType::File
+    }
+}
@@ -1,20 +1,30 @@
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::fs::Permissions;
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::os::unix::fs::PermissionsExt;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use std::rc::Rc;
+use std::time::SystemTime;
 
-use crate::{DirEntry, File, FileSystem, FileType};
+use crate::{DirEntry, File, FileSystem, FileType, Metadata, VirtualPath};
 use crate::error::ChiconError;
 
+// Maximum number of symbolic links followed to resolve a path, like Linux
+const MAX_SYMLINK_HOPS: usize = 40;
+
 /// Structure implementing `FileSystem` trait to store on an in memory filesystem
 #[derive(Default, Clone)]
 pub struct MemFileSystem {
     children: RefCell<HashMap<String, MemDirEntry>>,
 }
 
+// Normalized path relative to the root of the in memory filesystem, names are stored as strings
+// so the bytes of a name which isn't valid UTF-8 are encoded
+fn mem_path<P: AsRef<Path>>(path: P) -> Result<PathBuf, ChiconError> {
+    Ok(PathBuf::from(VirtualPath::new(path)?.to_key()))
+}
+
 impl FileSystem for MemFileSystem {
     type FSError = ChiconError;
     type File = MemFile;
@@ -24,35 +34,55 @@ impl FileSystem for MemFileSystem {
         Ok(())
     }
     fn create_file<P: AsRef<Path>>(&self, path: P) -> Result<Self::File, Self::FSError> {
-        let path = path.as_ref();
-        self.insert_file(PathBuf::from(path))
+        let path = self.resolve(&mem_path(path)?)?;
+        let file = self.insert_file(path)?;
+        // Like std::fs::File::create, truncate the file if it already exists
+        {
+            let mut file_internal = file.0.try_borrow_mut()?;
+            file_internal.content.clear();
+            file_internal.offset = 0;
+            file_internal.bytes_read = 0;
+            file_internal.modified = SystemTime::now();
+        }
+
+        Ok(file)
     }
     fn create_dir<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
-        let path = path.as_ref();
-        self.insert_dir(PathBuf::from(path), false)?;
+        let path = mem_path(path)?;
+        self.insert_dir(path.clone(), false)?;
 
         Ok(())
     }
     fn create_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
-        let path = path.as_ref();
-        self.insert_dir(PathBuf::from(path), true)?;
+        let path = mem_path(path)?;
+        self.insert_dir(path.clone(), true)?;
 
         Ok(())
     }
     fn open_file<P: AsRef<Path>>(&self, path: P) -> Result<Self::File, Self::FSError> {
-        let path = path.as_ref();
-        if let Some(entry) = self.get_from_relative_path(PathBuf::from(path)) {
+        let path = self.resolve(&mem_path(path)?)?;
+        if let Some(entry) = self.get_from_relative_path(path.clone()) {
             match entry {
-                MemDirEntry::File(file) => Ok(file),
-                _ => Err(ChiconError::MemFileNotFound(PathBuf::from(path))),
+                MemDirEntry::File(file) => {
+                    {
+                        let mut file_internal = file.0.try_borrow_mut()?;
+                        file_internal.offset = 0;
+                        file_internal.bytes_read = 0;
+                    }
+                    Ok(file)
+                }
+                _ => Err(ChiconError::MemFileNotFound(path)),
             }
         } else {
-            Err(ChiconError::MemFileNotFound(PathBuf::from(path)))
+            Err(ChiconError::MemFileNotFound(path))
         }
     }
     fn read_dir<P: AsRef<Path>>(&self, path: P) -> Result<Vec<Self::DirEntry>, Self::FSError> {
-        let path = path.as_ref();
-        if let Some(entry) = self.get_from_relative_path(PathBuf::from(path)) {
+        let path = self.resolve(&mem_path(path)?)?;
+        if path.as_os_str().is_empty() {
+            return Ok(self.children.try_borrow()?.values().cloned().collect());
+        }
+        if let Some(entry) = self.get_from_relative_path(path.clone()) {
             match entry {
                 MemDirEntry::Directory(dir) => {
                     if let Some(children) = &dir.0.try_borrow()?.children {
@@ -61,28 +91,151 @@ impl FileSystem for MemFileSystem {
                         Ok(Vec::new())
                     }
                 }
-                _ => Err(ChiconError::MemFileNotFound(PathBuf::from(path))),
+                _ => Err(ChiconError::MemFileNotFound(path)),
             }
         } else {
-            Err(ChiconError::MemFileNotFound(PathBuf::from(path)))
+            Err(ChiconError::MemFileNotFound(path))
         }
     }
     fn remove_file<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
-        let path = path.as_ref();
-        self.remove(PathBuf::from(path), FileType::File, false)
+        let path = mem_path(path)?;
+        if let Some(MemDirEntry::Symlink(_)) = self.get_from_relative_path(path.clone()) {
+            return self.remove(path.clone(), FileType::Symlink, false);
+        }
+        self.remove(path.clone(), FileType::File, false)
     }
     fn remove_dir<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
-        let path = path.as_ref();
-        self.remove(PathBuf::from(path), FileType::Directory, false)
+        let path = mem_path(path)?;
+        self.remove(path.clone(), FileType::Directory, false)
     }
     fn remove_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
-        let path = path.as_ref();
-        self.remove(PathBuf::from(path), FileType::Directory, true)
+        let path = mem_path(path)?;
+        self.remove(path.clone(), FileType::Directory, true)
     }
     fn rename<P: AsRef<Path>>(&self, from: P, to: P) -> Result<(), Self::FSError> {
-        let from = from.as_ref();
-        let to = to.as_ref();
-        self.rename_internal(PathBuf::from(from), PathBuf::from(to))
+        let from = mem_path(from)?;
+        let to = mem_path(to)?;
+        self.rename_internal(from.clone(), to.clone())
+    }
+    fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata, Self::FSError> {
+        let path = self.resolve(&mem_path(path)?)?;
+        if path.as_os_str().is_empty() {
+            return Ok(Metadata {
+                file_type: FileType::Directory,
+                len: 0,
+                modified: None,
+                permissions: None,
+                etag: None,
+            });
+        }
+        match self.get_from_relative_path(path.clone()) {
+            Some(MemDirEntry::File(file)) => {
+                let file_internal = file.0.try_borrow()?;
+                Ok(Metadata {
+                    file_type: FileType::File,
+                    len: file_internal.content.len() as u64,
+                    modified: Some(file_internal.modified),
+                    permissions: Some(file_internal.perm.clone()),
+                    etag: None,
+                })
+            }
+            Some(MemDirEntry::Directory(dir)) => Ok(Metadata {
+                file_type: FileType::Directory,
+                len: 0,
+                modified: None,
+                permissions: Some(dir.0.try_borrow()?.perm.clone()),
+                etag: None,
+            }),
+            Some(MemDirEntry::Symlink(_)) | None => Err(ChiconError::MemFileNotFound(path)),
+        }
+    }
+    fn set_modified<P: AsRef<Path>>(&self, path: P, modified: SystemTime) -> Result<(), Self::FSError> {
+        let path = self.resolve(&mem_path(path)?)?;
+        match self.get_from_relative_path(path.clone()) {
+            Some(MemDirEntry::File(file)) => {
+                file.0.try_borrow_mut()?.modified = modified;
+                Ok(())
+            }
+            // Modification times of directories aren't kept
+            Some(_) => Ok(()),
+            None => Err(ChiconError::MemFileNotFound(path)),
+        }
+    }
+    fn symlink<P: AsRef<Path>>(&self, target: P, link: P) -> Result<(), Self::FSError> {
+        self.insert_symlink(mem_path(link)?, PathBuf::from(target.as_ref()))
+    }
+    fn read_link<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Self::FSError> {
+        let path = mem_path(path)?;
+        match self.get_from_relative_path(path.clone()) {
+            Some(MemDirEntry::Symlink(symlink)) => Ok(symlink.0.try_borrow()?.target.clone()),
+            Some(_) => Err(ChiconError::BadPath),
+            None => Err(ChiconError::MemFileNotFound(path.clone())),
+        }
+    }
+    fn symlink_metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata, Self::FSError> {
+        let path = mem_path(path)?;
+        match self.get_from_relative_path(path.clone()) {
+            Some(MemDirEntry::Symlink(symlink)) => Ok(Metadata {
+                file_type: FileType::Symlink,
+                len: symlink.0.try_borrow()?.target.as_os_str().len() as u64,
+                modified: None,
+                permissions: Some(Permissions::from_mode(0o777)),
+                etag: None,
+            }),
+            _ => self.metadata(path),
+        }
+    }
+    fn canonicalize<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Self::FSError> {
+        let resolved = self.resolve(&mem_path(path)?)?;
+        if !resolved.as_os_str().is_empty() && self.get_from_relative_path(resolved.clone()).is_none() {
+            return Err(ChiconError::MemFileNotFound(resolved));
+        }
+
+        Ok(resolved)
+    }
+    fn copy<P: AsRef<Path>>(&self, from: P, to: P) -> Result<(), Self::FSError> {
+        let from = self.resolve(&mem_path(from)?)?;
+        let to = self.resolve(&mem_path(to)?)?;
+        let file_internal = match self.get_from_relative_path(from.clone()) {
+            Some(MemDirEntry::File(file)) => file.0.try_borrow()?.clone(),
+            _ => return Err(ChiconError::MemFileNotFound(from)),
+        };
+
+        let file = self.insert_file(to)?;
+        let mut new_file_internal = file.0.try_borrow_mut()?;
+        new_file_internal.content = file_internal.content;
+        new_file_internal.perm = file_internal.perm;
+        new_file_internal.offset = 0;
+        new_file_internal.bytes_read = 0;
+        new_file_internal.modified = SystemTime::now();
+
+        Ok(())
+    }
+    fn copy_dir_all<P: AsRef<Path>>(&self, from: P, to: P) -> Result<(), Self::FSError> {
+        let from = mem_path(from)?;
+        let to = mem_path(to)?;
+        let children = match self.get_from_relative_path(from.clone()) {
+            Some(MemDirEntry::Directory(dir)) => {
+                dir.0.try_borrow()?.children.clone().unwrap_or_default()
+            }
+            _ => return Err(ChiconError::MemDirNotFound(from.clone())),
+        };
+
+        self.insert_dir(to.clone(), true)?;
+        for (name, child) in children {
+            match child {
+                MemDirEntry::File(_) => self.copy(from.join(&name), to.join(&name))?,
+                MemDirEntry::Directory(_) => {
+                    self.copy_dir_all(from.join(&name), to.join(&name))?
+                }
+                MemDirEntry::Symlink(symlink) => {
+                    let target = symlink.0.try_borrow()?.target.clone();
+                    self.symlink(target, to.join(&name))?
+                }
+            }
+        }
+
+        Ok(())
     }
 }
 
@@ -107,6 +260,90 @@ impl MemFileSystem {
         child.get_from_relative_path(path_iter.collect())
     }
 
+    // Follow the symbolic links met on every component of `path`, up to MAX_SYMLINK_HOPS of them.
+    // The components after a missing one are kept as is, so that the path of a new file can be resolved.
+    fn resolve(&self, path: &Path) -> Result<PathBuf, ChiconError> {
+        let mut pending: VecDeque<PathBuf> = path
+            .components()
+            .map(|component| PathBuf::from(component.as_os_str()))
+            .collect();
+        let mut resolved = PathBuf::new();
+        let mut hops = 0;
+        let mut missing = false;
+
+        while let Some(component) = pending.pop_front() {
+            match component.components().next() {
+                Some(Component::ParentDir) => {
+                    resolved.pop();
+                    continue;
+                }
+                Some(Component::Normal(name)) => resolved.push(name),
+                _ => continue,
+            }
+            if missing {
+                continue;
+            }
+
+            match self.get_from_relative_path(resolved.clone()) {
+                Some(MemDirEntry::Symlink(symlink)) => {
+                    hops += 1;
+                    if hops > MAX_SYMLINK_HOPS {
+                        return Err(ChiconError::BadPath);
+                    }
+                    let target = symlink.0.try_borrow()?.target.clone();
+                    resolved.pop();
+                    if target.has_root() {
+                        resolved = PathBuf::new();
+                    }
+                    for component in target.components().rev() {
+                        pending.push_front(PathBuf::from(component.as_os_str()));
+                    }
+                }
+                Some(_) => {}
+                None => missing = true,
+            }
+        }
+
+        Ok(resolved)
+    }
+
+    fn insert_symlink(&self, path: PathBuf, target: PathBuf) -> Result<(), ChiconError> {
+        let name = path
+            .file_name()
+            .ok_or(ChiconError::BadPath)?
+            .to_string_lossy()
+            .into_owned();
+        let symlink_internal = MemSymlinkInternal {
+            name: name.clone(),
+            complete_path: path.clone(),
+            target,
+        };
+        let symlink = MemDirEntry::Symlink(MemSymlink(Rc::new(RefCell::new(symlink_internal))));
+
+        let parent = path.parent().map(PathBuf::from).unwrap_or_default();
+        if parent.as_os_str().is_empty() {
+            let mut children = self.children.try_borrow_mut()?;
+            if children.contains_key(&name) {
+                return Err(ChiconError::BadPath);
+            }
+            children.insert(name, symlink);
+            return Ok(());
+        }
+
+        match self.get_from_relative_path(parent.clone()) {
+            Some(MemDirEntry::Directory(dir)) => {
+                let mut dir_internal = dir.0.try_borrow_mut()?;
+                let children = dir_internal.children.get_or_insert_with(HashMap::new);
+                if children.contains_key(&name) {
+                    return Err(ChiconError::BadPath);
+                }
+                children.insert(name, symlink);
+                Ok(())
+            }
+            _ => Err(ChiconError::MemDirNotFound(parent)),
+        }
+    }
+
     fn insert_file(&self, path: PathBuf) -> Result<MemFile, ChiconError> {
         let complete_path = path.clone();
         let mut path_iter = path.iter();
@@ -119,6 +356,7 @@ impl MemFileSystem {
             match entry {
                 MemDirEntry::Directory(dir) => dir.insert_file(path_iter.collect(), complete_path),
                 MemDirEntry::File(file) => Ok(file.clone()),
+                MemDirEntry::Symlink(_) => Err(ChiconError::BadPath),
             }
         } else {
             // create file
@@ -132,6 +370,7 @@ impl MemFileSystem {
                     complete_path,
                     offset: 0,
                     bytes_read: 0,
+                    modified: SystemTime::now(),
                 };
                 let file = MemFile(Rc::new(RefCell::new(file_internal)));
 
@@ -160,7 +399,7 @@ impl MemFileSystem {
                         Ok(dir.clone())
                     }
                 }
-                MemDirEntry::File(_) => Err(ChiconError::BadPath),
+                MemDirEntry::File(_) | MemDirEntry::Symlink(_) => Err(ChiconError::BadPath),
             }
         } else if path_iter.clone().peekable().peek().is_some() {
             if force {
@@ -289,6 +528,19 @@ impl MemFileSystem {
                         );
                         return Ok(());
                     }
+                    MemDirEntry::Symlink(symlink_entry) => {
+                        {
+                            let mut symlink_internal = symlink_entry.0.try_borrow_mut()?;
+                            symlink_internal.complete_path = complete_path;
+                        }
+                        let symlink_entry_cloned = symlink_entry.clone();
+                        children.remove(&current_path.to_string_lossy().into_owned());
+                        children.insert(
+                            current_new_path.to_string_lossy().into_owned(),
+                            MemDirEntry::Symlink(symlink_entry_cloned),
+                        );
+                        return Ok(());
+                    }
                 }
             }
         } else {
@@ -307,6 +559,7 @@ struct MemFileInternal {
     perm: Permissions,
     offset: u64,
     bytes_read: u64,
+    modified: SystemTime,
 }
 
 /// Structure implementing File trait to represent a file on an in memory filesystem
@@ -323,54 +576,33 @@ impl File for MemFile {
 
 impl Read for MemFile {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
-        let cloned_content: Vec<u8>;
-        {
-            let content = &self
-                .0
-                .try_borrow()
-                .map_err(|err| {
-                    std::io::Error::new(
-                        std::io::ErrorKind::Other,
-                        format!("cannot borrow the file to fill the content : {:?}", err),
-                    )
-                })?
-                .content;
-            cloned_content = content.clone();
-        }
         let mut mem_file = self.0.try_borrow_mut().map_err(|err| {
             std::io::Error::new(
                 std::io::ErrorKind::Other,
                 format!("cannot borrow mut the file to fill the content : {:?}", err),
             )
         })?;
-        let mut content_slice = if mem_file.bytes_read == 0 {
-            if mem_file.offset >= cloned_content.len() as u64 {
-                return Ok(0);
-            }
-            &cloned_content[(mem_file.offset as usize)..]
-        } else {
-            cloned_content.as_slice()
-        };
-        let nb = content_slice.read(buf)?;
+        if mem_file.offset >= mem_file.content.len() as u64 {
+            return Ok(0);
+        }
+        let nb = (&mem_file.content[(mem_file.offset as usize)..]).read(buf)?;
 
+        mem_file.offset += nb as u64;
         mem_file.bytes_read += nb as u64;
-        mem_file.content = content_slice.to_vec();
         Ok(nb)
     }
 }
 
 impl Write for MemFile {
     fn write(&mut self, buf: &[u8]) -> Result<usize, std::io::Error> {
-        self.0
-            .try_borrow_mut()
-            .map_err(|_| {
-                std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    "cannot borrow mut the file to write",
-                )
-            })?
-            .content
-            .write(buf)
+        let mut mem_file = self.0.try_borrow_mut().map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "cannot borrow mut the file to write",
+            )
+        })?;
+        mem_file.modified = SystemTime::now();
+        mem_file.content.write(buf)
     }
     fn flush(&mut self) -> Result<(), std::io::Error> {
         self.0
@@ -433,6 +665,7 @@ impl Seek for MemFile {
 pub enum MemDirEntry {
     File(MemFile),
     Directory(MemDirectory),
+    Symlink(MemSymlink),
 }
 
 impl DirEntry for MemDirEntry {
@@ -440,8 +673,9 @@ impl DirEntry for MemDirEntry {
 
     fn path(&self) -> Result<PathBuf, Self::FSError> {
         match self {
-            MemDirEntry::Directory(dir) => Ok(PathBuf::from(dir.0.try_borrow()?.name.clone())),
-            MemDirEntry::File(file) => Ok(file.0.try_borrow()?.complete_path.clone()),
+            MemDirEntry::Directory(dir) => Ok(VirtualPath::from_key(&dir.0.try_borrow()?.name)),
+            MemDirEntry::File(file) => Ok(VirtualPath::from_key(&file.0.try_borrow()?.complete_path.to_string_lossy())),
+            MemDirEntry::Symlink(symlink) => Ok(VirtualPath::from_key(&symlink.0.try_borrow()?.complete_path.to_string_lossy())),
         }
     }
 
@@ -449,6 +683,7 @@ impl DirEntry for MemDirEntry {
         match self {
             MemDirEntry::Directory(_) => Ok(FileType::Directory),
             MemDirEntry::File(_) => Ok(FileType::File),
+            MemDirEntry::Symlink(_) => Ok(FileType::Symlink),
         }
     }
 }
@@ -477,6 +712,13 @@ impl MemDirEntry {
                     dir.get_from_relative_path(path)
                 }
             }
+            MemDirEntry::Symlink(symlink) => {
+                if symlink.0.try_borrow().ok()?.name == current_path.to_string_lossy().into_owned() {
+                    Some(self.clone())
+                } else {
+                    None
+                }
+            }
         }
     }
 
@@ -487,7 +729,7 @@ impl MemDirEntry {
         force: bool,
     ) -> Result<(), ChiconError> {
         match self {
-            MemDirEntry::File(_) => Err(ChiconError::BadPath),
+            MemDirEntry::File(_) | MemDirEntry::Symlink(_) => Err(ChiconError::BadPath),
             MemDirEntry::Directory(dir) => dir.remove(path, entry_type, force),
         }
     }
@@ -501,11 +743,22 @@ impl MemDirEntry {
         // replace
         match self {
             MemDirEntry::Directory(dir_entry) => dir_entry.rename(path, new_path, complete_path),
-            MemDirEntry::File(_) => Err(ChiconError::BadPath),
+            MemDirEntry::File(_) | MemDirEntry::Symlink(_) => Err(ChiconError::BadPath),
         }
     }
 }
 
+#[derive(Clone)]
+struct MemSymlinkInternal {
+    complete_path: PathBuf,
+    name: String,
+    target: PathBuf,
+}
+
+/// Structure representing a symbolic link on an in memory filesystem
+#[derive(Clone)]
+pub struct MemSymlink(Rc<RefCell<MemSymlinkInternal>>);
+
 #[derive(Clone)]
 struct MemDirectoryInternal {
     complete_path: PathBuf,
@@ -553,6 +806,7 @@ impl MemDirectory {
                         dir.insert_file(path_iter.collect(), complete_path)
                     }
                     MemDirEntry::File(file) => Ok(file.clone()),
+                    MemDirEntry::Symlink(_) => Err(ChiconError::BadPath),
                 }
             } else {
                 // create file
@@ -566,6 +820,7 @@ impl MemDirectory {
                         complete_path,
                         offset: 0,
                         bytes_read: 0,
+                        modified: SystemTime::now(),
                     };
                     let file = MemFile(Rc::new(RefCell::new(file_internal)));
 
@@ -605,7 +860,7 @@ impl MemDirectory {
                             Ok(dir.clone())
                         }
                     }
-                    MemDirEntry::File(_) => Err(ChiconError::BadPath),
+                    MemDirEntry::File(_) | MemDirEntry::Symlink(_) => Err(ChiconError::BadPath),
                 }
             } else {
                 // create file
@@ -739,6 +994,19 @@ impl MemDirectory {
                         );
                         Ok(())
                     }
+                    MemDirEntry::Symlink(symlink_entry) => {
+                        {
+                            let mut symlink_internal = symlink_entry.0.try_borrow_mut()?;
+                            symlink_internal.complete_path = complete_path;
+                        }
+                        let symlink_entry_cloned = symlink_entry.clone();
+                        children.remove(&current_path.to_string_lossy().into_owned());
+                        children.insert(
+                            current_new_path.to_string_lossy().into_owned(),
+                            MemDirEntry::Symlink(symlink_entry_cloned),
+                        );
+                        Ok(())
+                    }
                 }
             }
         } else {
@@ -992,6 +1260,245 @@ mod tests {
         mem_fs.remove_dir_all("share/testmemreaddir").unwrap();
     }
 
+    #[test]
+    fn test_copy() {
+        let mem_fs = MemFileSystem::new();
+        mem_fs.create_dir_all("share/testmemcopy").unwrap();
+        {
+            let mut file = mem_fs.create_file("share/testmemcopy/myfile").unwrap();
+            file.write_all(b"coucoutoi").unwrap();
+        }
+
+        mem_fs
+            .copy("share/testmemcopy/myfile", "share/testmemcopy/myfilebis")
+            .unwrap();
+        mem_fs.remove_file("share/testmemcopy/myfile").unwrap();
+
+        let mut content = String::new();
+        mem_fs
+            .open_file("share/testmemcopy/myfilebis")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(String::from("coucoutoi"), content);
+        assert!(mem_fs.copy("share/testmemcopy/myfile", "share/other").is_err());
+    }
+
+    #[test]
+    fn test_atomic_write() {
+        let mem_fs = MemFileSystem::new();
+        mem_fs.create_dir_all("share/testmematomic").unwrap();
+
+        mem_fs
+            .atomic_write("share/testmematomic/myfile", |file| {
+                file.write_all(b"coucoutoi")?;
+                Ok(())
+            })
+            .unwrap();
+        let res = mem_fs.atomic_write("share/testmematomic/myfile", |_| Err(ChiconError::BadPath));
+
+        assert!(res.is_err());
+        let mut content = String::new();
+        mem_fs
+            .open_file("share/testmematomic/myfile")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(String::from("coucoutoi"), content);
+        assert_eq!(mem_fs.read_dir("share/testmematomic").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_symlink() {
+        let mem_fs = MemFileSystem::new();
+        mem_fs.create_dir_all("share/testmemsymlink/test").unwrap();
+        {
+            let mut file = mem_fs
+                .create_file("share/testmemsymlink/test/myfile")
+                .unwrap();
+            file.write_all(b"coucoutoi").unwrap();
+        }
+
+        mem_fs
+            .symlink("test/myfile", "share/testmemsymlink/mylink")
+            .unwrap();
+        mem_fs
+            .symlink("../testmemsymlink/test", "share/testmemsymlink/mydirlink")
+            .unwrap();
+
+        assert_eq!(
+            mem_fs.read_link("share/testmemsymlink/mylink").unwrap(),
+            PathBuf::from("test/myfile")
+        );
+        assert!(mem_fs
+            .metadata("share/testmemsymlink/mylink")
+            .unwrap()
+            .is_file());
+        assert_eq!(
+            mem_fs
+                .symlink_metadata("share/testmemsymlink/mylink")
+                .unwrap()
+                .file_type,
+            FileType::Symlink
+        );
+        let mut content = String::new();
+        mem_fs
+            .open_file("share/testmemsymlink/mylink")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(String::from("coucoutoi"), content);
+        assert_eq!(
+            mem_fs
+                .read_dir("share/testmemsymlink/mydirlink")
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(mem_fs
+            .symlink("test/myfile", "share/testmemsymlink/mylink")
+            .is_err());
+
+        // Symbolic links are followed in the middle of a path too
+        content.clear();
+        mem_fs
+            .open_file("share/testmemsymlink/mydirlink/myfile")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(String::from("coucoutoi"), content);
+        mem_fs
+            .create_file("share/testmemsymlink/mydirlink/mynewfile")
+            .unwrap();
+        assert!(mem_fs
+            .metadata("share/testmemsymlink/test/mynewfile")
+            .unwrap()
+            .is_file());
+        mem_fs
+            .symlink("loop", "share/testmemsymlink/loop")
+            .unwrap();
+        assert!(mem_fs.open_file("share/testmemsymlink/loop/myfile").is_err());
+
+        mem_fs.remove_file("share/testmemsymlink/mylink").unwrap();
+        assert!(mem_fs.read_link("share/testmemsymlink/mylink").is_err());
+        assert!(mem_fs
+            .metadata("share/testmemsymlink/test/myfile")
+            .is_ok());
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        let mem_fs = MemFileSystem::new();
+        mem_fs.create_dir_all("share/testmemcanon/test").unwrap();
+        mem_fs.create_file("share/testmemcanon/test/myfile").unwrap();
+        mem_fs
+            .symlink("test", "share/testmemcanon/mydirlink")
+            .unwrap();
+        mem_fs
+            .symlink("/share/testmemcanon/mydirlink/myfile", "share/mylink")
+            .unwrap();
+
+        assert_eq!(
+            mem_fs
+                .canonicalize("./share/testmemcanon/mydirlink/../test/myfile")
+                .unwrap(),
+            PathBuf::from("share/testmemcanon/test/myfile")
+        );
+        assert_eq!(
+            mem_fs.canonicalize("share/mylink").unwrap(),
+            PathBuf::from("share/testmemcanon/test/myfile")
+        );
+        assert!(mem_fs.canonicalize("share/notfound").is_err());
+    }
+
+    #[test]
+    fn test_copy_dir_all() {
+        let mem_fs = MemFileSystem::new();
+        mem_fs.create_dir_all("share/testmemcopydir/test").unwrap();
+        {
+            let mut file = mem_fs
+                .create_file("share/testmemcopydir/test/myfile")
+                .unwrap();
+            file.write_all(b"coucoutoi").unwrap();
+        }
+
+        mem_fs
+            .copy_dir_all("share/testmemcopydir", "share/testmemcopydirbis")
+            .unwrap();
+        mem_fs.remove_dir_all("share/testmemcopydir").unwrap();
+
+        assert_eq!(
+            mem_fs.read_dir("share/testmemcopydirbis/test").unwrap().len(),
+            1
+        );
+        let mut content = String::new();
+        mem_fs
+            .open_file("share/testmemcopydirbis/test/myfile")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(String::from("coucoutoi"), content);
+    }
+
+    #[test]
+    fn test_metadata() {
+        let mem_fs = MemFileSystem::new();
+        mem_fs.create_dir_all("share/testmemmetadata").unwrap();
+        {
+            let mut file = mem_fs
+                .create_file("share/testmemmetadata/myfile")
+                .unwrap();
+            file.write_all(b"coucoutoi").unwrap();
+        }
+
+        assert!(mem_fs.metadata("share/testmemmetadata").unwrap().is_dir());
+        let metadata = mem_fs.metadata("share/testmemmetadata/myfile").unwrap();
+        assert!(metadata.is_file());
+        assert_eq!(metadata.len, 9);
+        assert!(mem_fs.metadata("share/notfound").is_err());
+    }
+
+    #[test]
+    fn test_checksum() {
+        let mem_fs = MemFileSystem::new();
+        {
+            let mut file = mem_fs.create_file("testchecksum.test").unwrap();
+            file.write_all(b"coucoutoi").unwrap();
+        }
+
+        assert_eq!(
+            mem_fs
+                .checksum("testchecksum.test", crate::ChecksumAlgorithm::Md5)
+                .unwrap(),
+            String::from("9b7c77a17e4906f5ed3fb8afdc129cfb")
+        );
+        assert!(mem_fs
+            .checksum("notfound.test", crate::ChecksumAlgorithm::Md5)
+            .is_err());
+    }
+
+    #[test]
+    fn test_read_twice() {
+        let mem_fs = MemFileSystem::new();
+        {
+            let mut file = mem_fs.create_file("testreadtwice.test").unwrap();
+            file.write_all(b"coucoutoi").unwrap();
+        }
+
+        for _ in 0..2 {
+            let mut content = String::new();
+            mem_fs
+                .open_file("testreadtwice.test")
+                .unwrap()
+                .read_to_string(&mut content)
+                .unwrap();
+            assert_eq!(String::from("coucoutoi"), content);
+        }
+
+        mem_fs.create_file("testreadtwice.test").unwrap();
+        assert_eq!(mem_fs.metadata("testreadtwice.test").unwrap().len, 0);
+    }
+
     #[test]
     fn test_rename() {
         let mem_fs = MemFileSystem::new();
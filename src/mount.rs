@@ -0,0 +1,625 @@
+use std::fs::Permissions;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::error::ChiconError;
+use crate::{ChecksumAlgorithm, DirEntry, File, FileSystem, FileType, Metadata, VirtualPath};
+
+// Object-safe counterpart of `FileSystem`, so that backends of different types can live in one mount table
+trait DynFileSystem<'a> {
+    fn chmod(&self, path: &Path, perm: Permissions) -> Result<(), ChiconError>;
+    fn create_file(&self, path: &Path) -> Result<MountFile<'a>, ChiconError>;
+    fn create_dir(&self, path: &Path) -> Result<(), ChiconError>;
+    fn create_dir_all(&self, path: &Path) -> Result<(), ChiconError>;
+    fn open_file(&self, path: &Path) -> Result<MountFile<'a>, ChiconError>;
+    fn read_dir(&self, path: &Path) -> Result<Vec<(String, FileType)>, ChiconError>;
+    fn remove_file(&self, path: &Path) -> Result<(), ChiconError>;
+    fn remove_dir(&self, path: &Path) -> Result<(), ChiconError>;
+    fn remove_dir_all(&self, path: &Path) -> Result<(), ChiconError>;
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), ChiconError>;
+    fn metadata(&self, path: &Path) -> Result<Metadata, ChiconError>;
+    fn symlink(&self, target: &Path, link: &Path) -> Result<(), ChiconError>;
+    fn read_link(&self, path: &Path) -> Result<PathBuf, ChiconError>;
+    fn symlink_metadata(&self, path: &Path) -> Result<Metadata, ChiconError>;
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf, ChiconError>;
+    fn set_modified(&self, path: &Path, modified: SystemTime) -> Result<(), ChiconError>;
+    fn copy(&self, from: &Path, to: &Path) -> Result<(), ChiconError>;
+    fn checksum(&self, path: &Path, algorithm: ChecksumAlgorithm) -> Result<String, ChiconError>;
+}
+
+struct Backend<F>(F);
+
+impl<'a, F> DynFileSystem<'a> for Backend<F>
+where
+    F: FileSystem,
+    F::File: 'a,
+    ChiconError: From<F::FSError>
+        + From<<F::File as File>::FSError>
+        + From<<F::DirEntry as DirEntry>::FSError>,
+{
+    fn chmod(&self, path: &Path, perm: Permissions) -> Result<(), ChiconError> {
+        Ok(self.0.chmod(path, perm)?)
+    }
+
+    fn create_file(&self, path: &Path) -> Result<MountFile<'a>, ChiconError> {
+        Ok(MountFile(Box::new(FileAdapter(self.0.create_file(path)?))))
+    }
+
+    fn create_dir(&self, path: &Path) -> Result<(), ChiconError> {
+        Ok(self.0.create_dir(path)?)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> Result<(), ChiconError> {
+        Ok(self.0.create_dir_all(path)?)
+    }
+
+    fn open_file(&self, path: &Path) -> Result<MountFile<'a>, ChiconError> {
+        Ok(MountFile(Box::new(FileAdapter(self.0.open_file(path)?))))
+    }
+
+    fn read_dir(&self, path: &Path) -> Result<Vec<(String, FileType)>, ChiconError> {
+        let mut dir_entries = Vec::new();
+        for entry in self.0.read_dir(path)? {
+            dir_entries.push((entry.name()?, entry.file_type()?));
+        }
+
+        Ok(dir_entries)
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<(), ChiconError> {
+        Ok(self.0.remove_file(path)?)
+    }
+
+    fn remove_dir(&self, path: &Path) -> Result<(), ChiconError> {
+        Ok(self.0.remove_dir(path)?)
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> Result<(), ChiconError> {
+        Ok(self.0.remove_dir_all(path)?)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), ChiconError> {
+        Ok(self.0.rename(from, to)?)
+    }
+
+    fn metadata(&self, path: &Path) -> Result<Metadata, ChiconError> {
+        Ok(self.0.metadata(path)?)
+    }
+
+    fn symlink(&self, target: &Path, link: &Path) -> Result<(), ChiconError> {
+        Ok(self.0.symlink(target, link)?)
+    }
+
+    fn read_link(&self, path: &Path) -> Result<PathBuf, ChiconError> {
+        Ok(self.0.read_link(path)?)
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> Result<Metadata, ChiconError> {
+        Ok(self.0.symlink_metadata(path)?)
+    }
+
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf, ChiconError> {
+        Ok(self.0.canonicalize(path)?)
+    }
+
+    fn set_modified(&self, path: &Path, modified: SystemTime) -> Result<(), ChiconError> {
+        Ok(self.0.set_modified(path, modified)?)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> Result<(), ChiconError> {
+        Ok(self.0.copy(from, to)?)
+    }
+
+    fn checksum(&self, path: &Path, algorithm: ChecksumAlgorithm) -> Result<String, ChiconError> {
+        Ok(self.0.checksum(path, algorithm)?)
+    }
+}
+
+struct Mount<'a> {
+    // Normalized prefix without root, empty for a filesystem mounted at `/`
+    prefix: PathBuf,
+    fs: Box<dyn DynFileSystem<'a> + 'a>,
+}
+
+/// Structure implementing `FileSystem` trait to gather several filesystems, of any backend, in one namespace.
+///
+/// Each filesystem is mounted at a path prefix and every operation is dispatched to the filesystem with the longest matching prefix,
+/// with the prefix removed from the path. Directories leading to mount points are listed even when no filesystem contains them.
+///
+/// ```
+/// use chicon::{FileSystem, MemFileSystem, MountFileSystem};
+///
+/// let mut mount_fs = MountFileSystem::new();
+/// mount_fs.mount("/tmp", MemFileSystem::new()).unwrap();
+/// mount_fs.create_file("/tmp/test.test").unwrap();
+/// ```
+#[derive(Default)]
+pub struct MountFileSystem<'a> {
+    mounts: Vec<Mount<'a>>,
+    atomic_rename: bool,
+}
+
+impl<'a> MountFileSystem<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Refuse renames between different mount points with `ChiconError::CrossMountRename` instead of copying and deleting, `false` by default
+    pub fn atomic_rename(mut self, atomic_rename: bool) -> Self {
+        self.atomic_rename = atomic_rename;
+        self
+    }
+
+    /// Mount `fs` at `prefix`, a prefix can't be used twice
+    pub fn mount<P, F>(&mut self, prefix: P, fs: F) -> Result<(), ChiconError>
+    where
+        P: AsRef<Path>,
+        F: FileSystem + 'a,
+        F::File: 'a,
+        ChiconError: From<F::FSError>
+            + From<<F::File as File>::FSError>
+            + From<<F::DirEntry as DirEntry>::FSError>,
+    {
+        let prefix = VirtualPath::new(prefix)?.relative().to_path_buf();
+        if self.mounts.iter().any(|mount| mount.prefix == prefix) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("a filesystem is already mounted at {:?}", prefix),
+            )
+            .into());
+        }
+        self.mounts.push(Mount {
+            prefix,
+            fs: Box::new(Backend(fs)),
+        });
+
+        Ok(())
+    }
+
+    /// Remove the filesystem mounted at `prefix`
+    pub fn unmount<P: AsRef<Path>>(&mut self, prefix: P) -> Result<(), ChiconError> {
+        let prefix = VirtualPath::new(prefix)?.relative().to_path_buf();
+        let mounts_len = self.mounts.len();
+        self.mounts.retain(|mount| mount.prefix != prefix);
+        if self.mounts.len() == mounts_len {
+            return Err(ChiconError::NotMounted(prefix));
+        }
+
+        Ok(())
+    }
+
+    // Mount with the longest prefix of `path` and the path inside this mount
+    fn resolve(&self, path: &VirtualPath) -> Option<(&Mount<'a>, PathBuf)> {
+        self.mounts
+            .iter()
+            .filter(|mount| path.relative().starts_with(&mount.prefix))
+            .max_by_key(|mount| mount.prefix.components().count())
+            .map(|mount| {
+                let inner_path = path.relative().strip_prefix(&mount.prefix).unwrap();
+                if inner_path.as_os_str().is_empty() {
+                    (mount, PathBuf::from("."))
+                } else {
+                    (mount, PathBuf::from(inner_path))
+                }
+            })
+    }
+
+    fn backend<P: AsRef<Path>>(&self, path: P) -> Result<(&Mount<'a>, PathBuf), ChiconError> {
+        let path = VirtualPath::new(path)?;
+        self.resolve(&path)
+            .ok_or_else(|| ChiconError::NotMounted(path.to_path_buf()))
+    }
+
+    // Names of the directories leading from `path` to the mount points below it
+    fn mount_children(&self, path: &VirtualPath) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .mounts
+            .iter()
+            .filter_map(|mount| mount.prefix.strip_prefix(path.relative()).ok())
+            .filter_map(|rest| rest.components().next())
+            .map(|component| component.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    fn is_mount_path(&self, path: &VirtualPath) -> bool {
+        self.mounts
+            .iter()
+            .any(|mount| mount.prefix.starts_with(path.relative()))
+    }
+
+    fn synthesized_metadata() -> Metadata {
+        Metadata {
+            file_type: FileType::Directory,
+            len: 0,
+            modified: None,
+            permissions: None,
+            etag: None,
+        }
+    }
+}
+
+impl<'a> FileSystem for MountFileSystem<'a> {
+    type FSError = ChiconError;
+    type File = MountFile<'a>;
+    type DirEntry = MountDirEntry;
+
+    fn chmod<P: AsRef<Path>>(&self, path: P, perm: Permissions) -> Result<(), Self::FSError> {
+        let (mount, inner_path) = self.backend(path)?;
+        mount.fs.chmod(&inner_path, perm)
+    }
+
+    fn create_file<P: AsRef<Path>>(&self, path: P) -> Result<Self::File, Self::FSError> {
+        let (mount, inner_path) = self.backend(path)?;
+        mount.fs.create_file(&inner_path)
+    }
+
+    fn create_dir<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
+        let (mount, inner_path) = self.backend(path)?;
+        mount.fs.create_dir(&inner_path)
+    }
+
+    fn create_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
+        let path = VirtualPath::new(path)?;
+        if self.is_mount_path(&path) {
+            return Ok(());
+        }
+        let (mount, inner_path) = self.backend(path.relative())?;
+        mount.fs.create_dir_all(&inner_path)
+    }
+
+    fn open_file<P: AsRef<Path>>(&self, path: P) -> Result<Self::File, Self::FSError> {
+        let (mount, inner_path) = self.backend(path)?;
+        mount.fs.open_file(&inner_path)
+    }
+
+    fn read_dir<P: AsRef<Path>>(&self, path: P) -> Result<Vec<Self::DirEntry>, Self::FSError> {
+        let path = VirtualPath::new(path)?;
+        let mount_children = self.mount_children(&path);
+        let mut dir_entries: Vec<(String, FileType)> = match self.resolve(&path) {
+            Some((mount, inner_path)) => match mount.fs.read_dir(&inner_path) {
+                Ok(dir_entries) => dir_entries,
+                Err(_) if !mount_children.is_empty() => Vec::new(),
+                Err(err) => return Err(err),
+            },
+            None if !mount_children.is_empty() => Vec::new(),
+            None => return Err(ChiconError::NotMounted(path.to_path_buf())),
+        };
+
+        // Mount points hide the entries they are mounted over
+        dir_entries.retain(|(name, _)| !mount_children.contains(name));
+        dir_entries.extend(
+            mount_children
+                .into_iter()
+                .map(|name| (name, FileType::Directory)),
+        );
+
+        let dir_path = path.to_path_buf();
+        Ok(dir_entries
+            .into_iter()
+            .map(|(name, file_type)| MountDirEntry {
+                path: dir_path.join(name),
+                file_type,
+            })
+            .collect())
+    }
+
+    fn remove_file<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
+        let (mount, inner_path) = self.backend(path)?;
+        mount.fs.remove_file(&inner_path)
+    }
+
+    fn remove_dir<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
+        let path = VirtualPath::new(path)?;
+        if self.is_mount_path(&path) {
+            return Err(ChiconError::Unsupported(String::from(
+                "remove a mount point",
+            )));
+        }
+        let (mount, inner_path) = self.backend(path.relative())?;
+        mount.fs.remove_dir(&inner_path)
+    }
+
+    fn remove_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
+        let path = VirtualPath::new(path)?;
+        if self.is_mount_path(&path) {
+            return Err(ChiconError::Unsupported(String::from(
+                "remove a mount point",
+            )));
+        }
+        let (mount, inner_path) = self.backend(path.relative())?;
+        mount.fs.remove_dir_all(&inner_path)
+    }
+
+    /// Renames between different mount points are done by copying and deleting, unless `atomic_rename` is set
+    fn rename<P: AsRef<Path>>(&self, from: P, to: P) -> Result<(), Self::FSError> {
+        let from = VirtualPath::new(from)?;
+        let to = VirtualPath::new(to)?;
+        if self.is_mount_path(&from) {
+            return Err(ChiconError::Unsupported(String::from(
+                "rename a mount point",
+            )));
+        }
+        let (from_mount, from_inner) = self.backend(from.relative())?;
+        let (to_mount, to_inner) = self.backend(to.relative())?;
+        if from_mount.prefix == to_mount.prefix {
+            return from_mount.fs.rename(&from_inner, &to_inner);
+        }
+        if self.atomic_rename {
+            return Err(ChiconError::CrossMountRename(
+                from.to_path_buf(),
+                to.to_path_buf(),
+            ));
+        }
+
+        match from_mount.fs.symlink_metadata(&from_inner)?.file_type {
+            FileType::Directory => {
+                self.copy_dir_all(from.to_path_buf(), to.to_path_buf())?;
+                from_mount.fs.remove_dir_all(&from_inner)
+            }
+            FileType::File => {
+                self.copy(from.to_path_buf(), to.to_path_buf())?;
+                from_mount.fs.remove_file(&from_inner)
+            }
+            FileType::Symlink => {
+                to_mount
+                    .fs
+                    .symlink(&from_mount.fs.read_link(&from_inner)?, &to_inner)?;
+                from_mount.fs.remove_file(&from_inner)
+            }
+        }
+    }
+
+    fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata, Self::FSError> {
+        let path = VirtualPath::new(path)?;
+        let metadata = self
+            .backend(path.relative())
+            .and_then(|(mount, inner_path)| mount.fs.metadata(&inner_path));
+        match metadata {
+            Err(_) if self.is_mount_path(&path) => Ok(Self::synthesized_metadata()),
+            metadata => metadata,
+        }
+    }
+
+    fn symlink<P: AsRef<Path>>(&self, target: P, link: P) -> Result<(), Self::FSError> {
+        let (mount, inner_link) = self.backend(link)?;
+        mount.fs.symlink(target.as_ref(), &inner_link)
+    }
+
+    fn read_link<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Self::FSError> {
+        let (mount, inner_path) = self.backend(path)?;
+        mount.fs.read_link(&inner_path)
+    }
+
+    fn symlink_metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata, Self::FSError> {
+        let path = VirtualPath::new(path)?;
+        let metadata = self
+            .backend(path.relative())
+            .and_then(|(mount, inner_path)| mount.fs.symlink_metadata(&inner_path));
+        match metadata {
+            Err(_) if self.is_mount_path(&path) => Ok(Self::synthesized_metadata()),
+            metadata => metadata,
+        }
+    }
+
+    /// The resolved path must stay inside the mount point of `path`
+    fn canonicalize<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Self::FSError> {
+        let (mount, inner_path) = self.backend(path)?;
+        let canonical_root = mount.fs.canonicalize(Path::new("."))?;
+        let canonical_path = mount.fs.canonicalize(&inner_path)?;
+        let relative = canonical_path
+            .strip_prefix(&canonical_root)
+            .map_err(|_| ChiconError::PathOutsideRoot(canonical_path.clone()))?;
+
+        Ok(Path::new("/").join(&mount.prefix).join(relative))
+    }
+
+    fn set_modified<P: AsRef<Path>>(
+        &self,
+        path: P,
+        modified: SystemTime,
+    ) -> Result<(), Self::FSError> {
+        let (mount, inner_path) = self.backend(path)?;
+        mount.fs.set_modified(&inner_path, modified)
+    }
+
+    fn copy<P: AsRef<Path>>(&self, from: P, to: P) -> Result<(), Self::FSError> {
+        let (from_mount, from_inner) = self.backend(from)?;
+        let (to_mount, to_inner) = self.backend(to)?;
+        if from_mount.prefix == to_mount.prefix {
+            return from_mount.fs.copy(&from_inner, &to_inner);
+        }
+
+        let mut src = from_mount.fs.open_file(&from_inner)?;
+        let mut dst = to_mount.fs.create_file(&to_inner)?;
+        io::copy(&mut src, &mut dst)?;
+        dst.sync_all()
+    }
+
+    fn checksum<P: AsRef<Path>>(
+        &self,
+        path: P,
+        algorithm: ChecksumAlgorithm,
+    ) -> Result<String, Self::FSError> {
+        let (mount, inner_path) = self.backend(path)?;
+        mount.fs.checksum(&inner_path, algorithm)
+    }
+}
+
+// Object-safe counterpart of `File`
+trait DynFile: Read + Write + Seek {
+    fn sync_all(&mut self) -> Result<(), ChiconError>;
+}
+
+struct FileAdapter<T>(T);
+
+impl<T> DynFile for FileAdapter<T>
+where
+    T: File,
+    ChiconError: From<T::FSError>,
+{
+    fn sync_all(&mut self) -> Result<(), ChiconError> {
+        Ok(self.0.sync_all()?)
+    }
+}
+
+impl<T: File> Read for FileAdapter<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl<T: File> Write for FileAdapter<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl<T: File> Seek for FileAdapter<T> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.0.seek(pos)
+    }
+}
+
+/// Structure implementing `File` trait to represent a file of any filesystem mounted in a `MountFileSystem`
+pub struct MountFile<'a>(Box<dyn DynFile + 'a>);
+
+impl<'a> File for MountFile<'a> {
+    type FSError = ChiconError;
+
+    fn sync_all(&mut self) -> Result<(), Self::FSError> {
+        self.0.sync_all()
+    }
+}
+
+impl<'a> Read for MountFile<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl<'a> Write for MountFile<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl<'a> Seek for MountFile<'a> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.0.seek(pos)
+    }
+}
+
+/// Structure implementing `DirEntry` trait to represent an entry of a `MountFileSystem`, mount points are listed as directories
+pub struct MountDirEntry {
+    path: PathBuf,
+    file_type: FileType,
+}
+
+impl DirEntry for MountDirEntry {
+    type FSError = ChiconError;
+
+    fn path(&self) -> Result<PathBuf, Self::FSError> {
+        Ok(self.path.clone())
+    }
+
+    fn file_type(&self) -> Result<FileType, Self::FSError> {
+        Ok(self.file_type.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemFileSystem;
+
+    fn names(dir_entries: Vec<MountDirEntry>) -> Vec<String> {
+        let mut names: Vec<String> = dir_entries
+            .iter()
+            .map(|entry| entry.name().unwrap())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_mount_dispatch() {
+        let mut mount_fs = MountFileSystem::new();
+        mount_fs.mount("/", MemFileSystem::new()).unwrap();
+        mount_fs
+            .mount("/data/archive", MemFileSystem::new())
+            .unwrap();
+        mount_fs.mount("/tmp", MemFileSystem::new()).unwrap();
+        assert!(mount_fs.mount("tmp", MemFileSystem::new()).is_err());
+
+        mount_fs.create_dir_all("/data/archive/2019").unwrap();
+        {
+            let mut file = mount_fs.create_file("/data/archive/2019/myfile").unwrap();
+            file.write_all(b"coucoutoi").unwrap();
+        }
+        mount_fs.create_file("/myfile").unwrap();
+
+        assert_eq!(
+            names(mount_fs.read_dir("/").unwrap()),
+            vec!["data", "myfile", "tmp"]
+        );
+        assert_eq!(names(mount_fs.read_dir("/data").unwrap()), vec!["archive"]);
+        assert!(mount_fs.metadata("/data").unwrap().is_dir());
+        assert_eq!(
+            names(mount_fs.read_dir("/data/archive/2019").unwrap()),
+            vec!["myfile"]
+        );
+        assert!(mount_fs.metadata("/tmp/myfile").is_err());
+        assert!(mount_fs.remove_dir_all("/data").is_err());
+
+        let mut content = String::new();
+        mount_fs
+            .open_file("/data/archive/2019/myfile")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(String::from("coucoutoi"), content);
+    }
+
+    #[test]
+    fn test_mount_rename() {
+        let mut mount_fs = MountFileSystem::new();
+        mount_fs.mount("/inbox", MemFileSystem::new()).unwrap();
+        mount_fs.mount("/archive", MemFileSystem::new()).unwrap();
+        {
+            let mut file = mount_fs.create_file("/inbox/myfile").unwrap();
+            file.write_all(b"coucoutoi").unwrap();
+        }
+
+        mount_fs.rename("/inbox/myfile", "/archive/myfile").unwrap();
+        assert!(mount_fs.metadata("/inbox/myfile").is_err());
+        let mut content = String::new();
+        mount_fs
+            .open_file("/archive/myfile")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(String::from("coucoutoi"), content);
+
+        let mount_fs = mount_fs.atomic_rename(true);
+        match mount_fs.rename("/archive/myfile", "/inbox/myfile") {
+            Err(ChiconError::CrossMountRename(_, _)) => {}
+            _ => panic!("a rename across mount points must be refused"),
+        }
+        mount_fs
+            .rename("/archive/myfile", "/archive/renamed")
+            .unwrap();
+        assert!(mount_fs.metadata("/archive/renamed").is_ok());
+    }
+}
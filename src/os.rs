@@ -1,8 +1,9 @@
 use std::fs::{File, OpenOptions, Permissions};
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
-use crate::{ChiconError, DirEntry, File as FsFile, FileSystem, FileType};
+use crate::{ChiconError, DirEntry, File as FsFile, FileSystem, FileType, Metadata};
 
 /// Structure implementing `FileSystem` trait to store on a local filesystem
 #[derive(Default)]
@@ -65,6 +66,34 @@ impl FileSystem for OsFileSystem {
     fn rename<P: AsRef<Path>>(&self, from: P, to: P) -> Result<(), Self::FSError> {
         std::fs::rename(from, to).map_err(|e| e.into())
     }
+
+    fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata, Self::FSError> {
+        Ok(Metadata::from(std::fs::metadata(path)?))
+    }
+
+    fn symlink<P: AsRef<Path>>(&self, target: P, link: P) -> Result<(), Self::FSError> {
+        std::os::unix::fs::symlink(target, link).map_err(|e| e.into())
+    }
+
+    fn read_link<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Self::FSError> {
+        std::fs::read_link(path).map_err(|e| e.into())
+    }
+
+    fn symlink_metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata, Self::FSError> {
+        Ok(Metadata::from(std::fs::symlink_metadata(path)?))
+    }
+
+    fn canonicalize<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Self::FSError> {
+        std::fs::canonicalize(path).map_err(|e| e.into())
+    }
+
+    fn set_modified<P: AsRef<Path>>(&self, path: P, modified: SystemTime) -> Result<(), Self::FSError> {
+        File::open(path)?.set_modified(modified).map_err(|e| e.into())
+    }
+
+    fn copy<P: AsRef<Path>>(&self, from: P, to: P) -> Result<(), Self::FSError> {
+        std::fs::copy(from, to).map(|_| ()).map_err(|e| e.into())
+    }
 }
 
 /// Structure implementing File trait to represent a file on a local filesystem
@@ -117,12 +146,12 @@ impl DirEntry for OsDirEntry {
 
     fn file_type(&self) -> Result<FileType, Self::FSError> {
         let file_type = self.0.file_type()?;
-        if file_type.is_dir() {
+        if file_type.is_symlink() {
+            Ok(FileType::Symlink)
+        } else if file_type.is_dir() {
             Ok(FileType::Directory)
-        } else if file_type.is_file() {
-            Ok(FileType::File)
         } else {
-            Ok(FileType::Symlink)
+            Ok(FileType::File)
         }
     }
 }
@@ -133,6 +162,26 @@ impl From<std::fs::DirEntry> for OsDirEntry {
     }
 }
 
+impl From<std::fs::Metadata> for Metadata {
+    fn from(metadata: std::fs::Metadata) -> Self {
+        let file_type = if metadata.file_type().is_symlink() {
+            FileType::Symlink
+        } else if metadata.is_dir() {
+            FileType::Directory
+        } else {
+            FileType::File
+        };
+
+        Metadata {
+            file_type,
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            permissions: Some(metadata.permissions()),
+            etag: None,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -269,6 +318,175 @@ mod tests {
         std::fs::remove_dir_all("testreaddirbis").unwrap();
     }
 
+    #[test]
+    fn test_copy() {
+        let os_fs = OsFileSystem::new();
+        {
+            let mut file = os_fs.create_file("testcopy.test").unwrap();
+            file.write_all(b"coucoutoi").unwrap();
+            file.sync_all().unwrap();
+        }
+
+        os_fs.copy("testcopy.test", "testcopybis.test").unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string("testcopybis.test").unwrap(),
+            String::from("coucoutoi")
+        );
+
+        std::fs::remove_file("testcopy.test").unwrap();
+        std::fs::remove_file("testcopybis.test").unwrap();
+    }
+
+    #[test]
+    fn test_atomic_write() {
+        let os_fs = OsFileSystem::new();
+        os_fs.create_dir_all("testatomicwrite").unwrap();
+
+        os_fs
+            .atomic_write("testatomicwrite/mytest.test", |file| {
+                file.write_all(b"coucoutoi")?;
+                Ok(())
+            })
+            .unwrap();
+        let res = os_fs.atomic_write("testatomicwrite/mytest.test", |file| {
+            file.write_all(b"other")?;
+            Err(ChiconError::BadPath)
+        });
+
+        assert!(res.is_err());
+        assert_eq!(
+            std::fs::read_to_string("testatomicwrite/mytest.test").unwrap(),
+            String::from("coucoutoi")
+        );
+        assert_eq!(os_fs.read_dir("testatomicwrite").unwrap().len(), 1);
+
+        std::fs::remove_dir_all("testatomicwrite").unwrap();
+    }
+
+    #[test]
+    fn test_symlink() {
+        let os_fs = OsFileSystem::new();
+        os_fs.create_dir_all("testsymlink/test").unwrap();
+        os_fs.create_file("testsymlink/test/mytest.test").unwrap();
+
+        os_fs
+            .symlink("test/mytest.test", "testsymlink/mylink")
+            .unwrap();
+
+        assert_eq!(
+            os_fs.read_link("testsymlink/mylink").unwrap(),
+            PathBuf::from("test/mytest.test")
+        );
+        assert!(os_fs.metadata("testsymlink/mylink").unwrap().is_file());
+        assert_eq!(
+            os_fs
+                .symlink_metadata("testsymlink/mylink")
+                .unwrap()
+                .file_type,
+            FileType::Symlink
+        );
+        let link_entry = os_fs
+            .read_dir("testsymlink")
+            .unwrap()
+            .into_iter()
+            .find(|entry| entry.name().unwrap() == "mylink")
+            .unwrap();
+        assert_eq!(link_entry.file_type().unwrap(), FileType::Symlink);
+
+        std::fs::remove_dir_all("testsymlink").unwrap();
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        let os_fs = OsFileSystem::new();
+        os_fs.create_dir_all("testcanonicalize/test").unwrap();
+        os_fs.symlink("test", "testcanonicalize/mylink").unwrap();
+
+        assert_eq!(
+            os_fs
+                .canonicalize("testcanonicalize/mylink/../mylink")
+                .unwrap(),
+            std::env::current_dir()
+                .unwrap()
+                .join("testcanonicalize/test")
+        );
+        assert!(os_fs.canonicalize("testcanonicalize/notfound").is_err());
+
+        std::fs::remove_dir_all("testcanonicalize").unwrap();
+    }
+
+    #[test]
+    fn test_native_paths() {
+        let os_fs = OsFileSystem::new();
+        os_fs.create_dir_all("testnativepaths/test/inner").unwrap();
+        os_fs
+            .create_file("testnativepaths/test/mytest.test")
+            .unwrap();
+        os_fs
+            .symlink("test/inner", "testnativepaths/mylink")
+            .unwrap();
+
+        // `..` is resolved by the OS after following the link, not lexically
+        assert!(os_fs
+            .metadata("testnativepaths/mylink/../mytest.test")
+            .unwrap()
+            .is_file());
+        // Relative paths going above the current directory are allowed
+        let current_dir = std::env::current_dir().unwrap();
+        let parent_path = Path::new("..")
+            .join(current_dir.file_name().unwrap())
+            .join("testnativepaths/test/mytest.test");
+        assert!(os_fs.metadata(parent_path).unwrap().is_file());
+
+        std::fs::remove_dir_all("testnativepaths").unwrap();
+    }
+
+    #[test]
+    fn test_copy_dir_all() {
+        let os_fs = OsFileSystem::new();
+        os_fs.create_dir_all("testcopydirall/test").unwrap();
+        {
+            let mut file = os_fs
+                .create_file("testcopydirall/test/mytest.test")
+                .unwrap();
+            file.write_all(b"coucoutoi").unwrap();
+            file.sync_all().unwrap();
+        }
+
+        os_fs
+            .copy_dir_all("testcopydirall", "testcopydirallbis")
+            .unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string("testcopydirallbis/test/mytest.test").unwrap(),
+            String::from("coucoutoi")
+        );
+        assert!(std::fs::read("testcopydirall/test/mytest.test").is_ok());
+
+        std::fs::remove_dir_all("testcopydirall").unwrap();
+        std::fs::remove_dir_all("testcopydirallbis").unwrap();
+    }
+
+    #[test]
+    fn test_metadata() {
+        let os_fs = OsFileSystem::new();
+        os_fs.create_dir_all("testmetadata").unwrap();
+        {
+            let mut file = os_fs.create_file("testmetadata/test.test").unwrap();
+            file.write_all(b"coucoutoi").unwrap();
+            file.sync_all().unwrap();
+        }
+
+        assert!(os_fs.metadata("testmetadata").unwrap().is_dir());
+        let metadata = os_fs.metadata("testmetadata/test.test").unwrap();
+        assert!(metadata.is_file());
+        assert_eq!(metadata.len, 9);
+        assert!(metadata.modified.is_some());
+
+        std::fs::remove_dir_all("testmetadata").unwrap();
+    }
+
     #[test]
     fn test_create_file_expects_error() {
         let os_fs = OsFileSystem::new();
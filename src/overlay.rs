@@ -0,0 +1,592 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fs::Permissions;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::error::ChiconError;
+use crate::{ChecksumAlgorithm, DirEntry, File, FileSystem, FileType, Metadata, VirtualPath};
+
+enum Layer {
+    Upper,
+    Lower,
+}
+
+/// Structure implementing `FileSystem` trait to stack a writable upper filesystem over a lower one which is never modified.
+///
+/// Reads fall through to the lower layer when the path doesn't exist in the upper one, writes always go to the upper layer
+/// and deletions of lower paths are recorded as whiteouts hiding them. `read_dir` merges both layers, the upper one winning.
+/// Files of the lower layer are opened read-only, call `copy_up` to modify them in place.
+pub struct OverlayFileSystem<U: FileSystem, L: FileSystem> {
+    upper: U,
+    lower: L,
+    // Lower paths deleted from the merged view
+    whiteouts: RefCell<HashSet<PathBuf>>,
+    // Paths recreated over a whiteout, the lower content under them stays hidden
+    opaques: RefCell<HashSet<PathBuf>>,
+}
+
+impl<U: FileSystem, L: FileSystem> OverlayFileSystem<U, L> {
+    pub fn new(upper: U, lower: L) -> Self {
+        OverlayFileSystem {
+            upper,
+            lower,
+            whiteouts: RefCell::new(HashSet::new()),
+            opaques: RefCell::new(HashSet::new()),
+        }
+    }
+
+    /// Get back the upper and the lower filesystems
+    pub fn into_inner(self) -> (U, L) {
+        (self.upper, self.lower)
+    }
+
+    fn hidden(&self, key: &Path) -> bool {
+        let whiteouts = self.whiteouts.borrow();
+        let opaques = self.opaques.borrow();
+        if whiteouts.contains(key) {
+            return true;
+        }
+
+        key.ancestors()
+            .skip(1)
+            .any(|ancestor| whiteouts.contains(ancestor) || opaques.contains(ancestor))
+    }
+
+    fn unwhiteout(&self, key: &Path) {
+        if self.whiteouts.borrow_mut().remove(key) {
+            self.opaques.borrow_mut().insert(PathBuf::from(key));
+        }
+    }
+
+    fn lower_metadata(&self, path: &VirtualPath) -> Option<Metadata> {
+        if self.hidden(path.relative()) {
+            return None;
+        }
+
+        self.lower.symlink_metadata(path.to_path_buf()).ok()
+    }
+
+    fn layer(&self, path: &VirtualPath) -> Result<Layer, ChiconError> {
+        if self.upper.symlink_metadata(path.to_path_buf()).is_ok() {
+            return Ok(Layer::Upper);
+        }
+        if self.lower_metadata(path).is_some() {
+            return Ok(Layer::Lower);
+        }
+
+        Err(ChiconError::BadPath)
+    }
+}
+
+impl<U, L> OverlayFileSystem<U, L>
+where
+    U: FileSystem,
+    L: FileSystem,
+    ChiconError: From<U::FSError>
+        + From<L::FSError>
+        + From<<U::File as File>::FSError>
+        + From<<L::File as File>::FSError>
+        + From<<U::DirEntry as DirEntry>::FSError>
+        + From<<L::DirEntry as DirEntry>::FSError>,
+{
+    /// Copy a path of the lower layer to the upper layer, to modify it without touching the lower layer.
+    /// Directories are copied without their content.
+    pub fn copy_up<P: AsRef<Path>>(&self, path: P) -> Result<(), ChiconError> {
+        let path = VirtualPath::new(path)?;
+        let native_path = path.to_path_buf();
+        if self.upper.symlink_metadata(&native_path).is_ok() {
+            return Ok(());
+        }
+        let metadata = self.lower_metadata(&path).ok_or(ChiconError::BadPath)?;
+
+        self.create_upper_parent(&path)?;
+        match metadata.file_type {
+            FileType::Directory => self.upper.create_dir(&native_path)?,
+            FileType::File => {
+                let mut src = self.lower.open_file(&native_path)?;
+                let mut dst = self.upper.create_file(&native_path)?;
+                io::copy(&mut src, &mut dst)?;
+                dst.sync_all()?;
+            }
+            FileType::Symlink => self
+                .upper
+                .symlink(self.lower.read_link(&native_path)?, native_path.clone())?,
+        }
+
+        Ok(())
+    }
+
+    fn merged_metadata(&self, path: &VirtualPath) -> Option<Metadata> {
+        match self.layer(path) {
+            Ok(Layer::Upper) => self.upper.symlink_metadata(path.to_path_buf()).ok(),
+            Ok(Layer::Lower) => self.lower_metadata(path),
+            Err(_) => None,
+        }
+    }
+
+    // Create in the upper layer the parent directories of `path` existing in the merged view
+    fn create_upper_parent(&self, path: &VirtualPath) -> Result<(), ChiconError> {
+        let parent = match path.parent() {
+            Some(parent) if !parent.is_root() => parent,
+            _ => return Ok(()),
+        };
+        if self.upper.symlink_metadata(parent.to_path_buf()).is_ok() {
+            return Ok(());
+        }
+        match self.merged_metadata(&parent) {
+            Some(ref metadata) if metadata.is_dir() => {
+                Ok(self.upper.create_dir_all(parent.to_path_buf())?)
+            }
+            _ => Err(ChiconError::BadPath),
+        }
+    }
+}
+
+impl<U, L> FileSystem for OverlayFileSystem<U, L>
+where
+    U: FileSystem,
+    L: FileSystem,
+    ChiconError: From<U::FSError>
+        + From<L::FSError>
+        + From<<U::File as File>::FSError>
+        + From<<L::File as File>::FSError>
+        + From<<U::DirEntry as DirEntry>::FSError>
+        + From<<L::DirEntry as DirEntry>::FSError>,
+{
+    type FSError = ChiconError;
+    type File = OverlayFile<U::File, L::File>;
+    type DirEntry = OverlayDirEntry;
+
+    fn chmod<P: AsRef<Path>>(&self, path: P, perm: Permissions) -> Result<(), Self::FSError> {
+        let path = VirtualPath::new(path)?;
+        self.copy_up(path.to_path_buf())?;
+
+        Ok(self.upper.chmod(path.to_path_buf(), perm)?)
+    }
+
+    fn create_file<P: AsRef<Path>>(&self, path: P) -> Result<Self::File, Self::FSError> {
+        let path = VirtualPath::new(path)?;
+        if let Some(metadata) = self.merged_metadata(&path) {
+            if metadata.is_dir() {
+                return Err(ChiconError::BadPath);
+            }
+        }
+        self.create_upper_parent(&path)?;
+        let file = self.upper.create_file(path.to_path_buf())?;
+        self.unwhiteout(path.relative());
+
+        Ok(OverlayFile::Upper(file))
+    }
+
+    fn create_dir<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
+        let path = VirtualPath::new(path)?;
+        if self.merged_metadata(&path).is_some() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, path.to_string()).into());
+        }
+        self.create_upper_parent(&path)?;
+        self.upper.create_dir(path.to_path_buf())?;
+        self.unwhiteout(path.relative());
+
+        Ok(())
+    }
+
+    fn create_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
+        let path = VirtualPath::new(path)?;
+        let mut current = if path.is_absolute() {
+            PathBuf::from("/")
+        } else {
+            PathBuf::new()
+        };
+        for component in path.relative().components() {
+            current.push(component);
+            match self.merged_metadata(&VirtualPath::new(&current)?) {
+                Some(ref metadata) if metadata.is_dir() => {}
+                Some(_) => return Err(ChiconError::BadPath),
+                None => self.create_dir(&current)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn open_file<P: AsRef<Path>>(&self, path: P) -> Result<Self::File, Self::FSError> {
+        let path = VirtualPath::new(path)?;
+        match self.layer(&path)? {
+            Layer::Upper => Ok(OverlayFile::Upper(
+                self.upper.open_file(path.to_path_buf())?,
+            )),
+            Layer::Lower => Ok(OverlayFile::Lower(
+                self.lower.open_file(path.to_path_buf())?,
+            )),
+        }
+    }
+
+    fn read_dir<P: AsRef<Path>>(&self, path: P) -> Result<Vec<Self::DirEntry>, Self::FSError> {
+        let path = VirtualPath::new(path)?;
+        let native_path = path.to_path_buf();
+        let mut dir_entries = Vec::new();
+        let mut names = HashSet::new();
+
+        let upper_metadata = self.upper.symlink_metadata(&native_path).ok();
+        if upper_metadata.is_some() {
+            for entry in self.upper.read_dir(&native_path)? {
+                let name = entry.name()?;
+                dir_entries.push(OverlayDirEntry {
+                    path: native_path.join(&name),
+                    file_type: entry.file_type()?,
+                });
+                names.insert(name);
+            }
+        }
+
+        let lower_metadata = if self.opaques.borrow().contains(path.relative()) {
+            None
+        } else {
+            self.lower_metadata(&path)
+        };
+        if upper_metadata.is_none() && lower_metadata.is_none() {
+            return Err(ChiconError::BadPath);
+        }
+        if lower_metadata.is_some() {
+            for entry in self.lower.read_dir(&native_path)? {
+                let name = entry.name()?;
+                if names.contains(&name)
+                    || self
+                        .whiteouts
+                        .borrow()
+                        .contains(path.join(&name)?.relative())
+                {
+                    continue;
+                }
+                dir_entries.push(OverlayDirEntry {
+                    path: native_path.join(&name),
+                    file_type: entry.file_type()?,
+                });
+            }
+        }
+
+        Ok(dir_entries)
+    }
+
+    fn remove_file<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
+        let path = VirtualPath::new(path)?;
+        let metadata = self.merged_metadata(&path).ok_or(ChiconError::BadPath)?;
+        if metadata.is_dir() {
+            return Err(ChiconError::BadPath);
+        }
+
+        if self.upper.symlink_metadata(path.to_path_buf()).is_ok() {
+            self.upper.remove_file(path.to_path_buf())?;
+        }
+        if self.lower_metadata(&path).is_some() {
+            self.whiteouts
+                .borrow_mut()
+                .insert(path.relative().to_path_buf());
+        }
+
+        Ok(())
+    }
+
+    fn remove_dir<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
+        let path = VirtualPath::new(path)?;
+        let metadata = self.merged_metadata(&path).ok_or(ChiconError::BadPath)?;
+        if !metadata.is_dir() {
+            return Err(ChiconError::BadPath);
+        }
+        if !self.read_dir(path.to_path_buf())?.is_empty() {
+            return Err(ChiconError::DirectoryNotEmpty);
+        }
+
+        if self.upper.symlink_metadata(path.to_path_buf()).is_ok() {
+            self.upper.remove_dir(path.to_path_buf())?;
+        }
+        if self.lower_metadata(&path).is_some() {
+            self.whiteouts
+                .borrow_mut()
+                .insert(path.relative().to_path_buf());
+        }
+        self.opaques.borrow_mut().remove(path.relative());
+
+        Ok(())
+    }
+
+    fn remove_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
+        let path = VirtualPath::new(path)?;
+        let key = path.relative();
+        self.merged_metadata(&path).ok_or(ChiconError::BadPath)?;
+
+        if self.upper.symlink_metadata(path.to_path_buf()).is_ok() {
+            self.upper.remove_dir_all(path.to_path_buf())?;
+        }
+        let lower_exists = self.lower_metadata(&path).is_some();
+        // Markers under the removed directory are useless now
+        self.whiteouts
+            .borrow_mut()
+            .retain(|whiteout| !whiteout.starts_with(key));
+        self.opaques
+            .borrow_mut()
+            .retain(|opaque| !opaque.starts_with(key));
+        if lower_exists {
+            self.whiteouts.borrow_mut().insert(key.to_path_buf());
+        }
+
+        Ok(())
+    }
+
+    /// Renaming a path of the lower layer copies it to the upper layer, this is not atomic
+    fn rename<P: AsRef<Path>>(&self, from: P, to: P) -> Result<(), Self::FSError> {
+        let from = VirtualPath::new(from)?;
+        let to = VirtualPath::new(to)?;
+        let metadata = self.merged_metadata(&from).ok_or(ChiconError::BadPath)?;
+
+        if self.lower_metadata(&from).is_none() {
+            self.create_upper_parent(&to)?;
+            self.upper.rename(from.to_path_buf(), to.to_path_buf())?;
+            self.unwhiteout(to.relative());
+            return Ok(());
+        }
+
+        match metadata.file_type {
+            FileType::Directory => {
+                self.copy_dir_all(from.to_path_buf(), to.to_path_buf())?;
+                self.remove_dir_all(from.to_path_buf())
+            }
+            FileType::File => {
+                self.copy(from.to_path_buf(), to.to_path_buf())?;
+                self.remove_file(from.to_path_buf())
+            }
+            FileType::Symlink => {
+                self.symlink(self.read_link(from.to_path_buf())?, to.to_path_buf())?;
+                self.remove_file(from.to_path_buf())
+            }
+        }
+    }
+
+    fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata, Self::FSError> {
+        let path = VirtualPath::new(path)?;
+        match self.layer(&path)? {
+            Layer::Upper => Ok(self.upper.metadata(path.to_path_buf())?),
+            Layer::Lower => Ok(self.lower.metadata(path.to_path_buf())?),
+        }
+    }
+
+    fn set_modified<P: AsRef<Path>>(
+        &self,
+        path: P,
+        modified: SystemTime,
+    ) -> Result<(), Self::FSError> {
+        let path = VirtualPath::new(path)?;
+        self.copy_up(path.to_path_buf())?;
+
+        Ok(self.upper.set_modified(path.to_path_buf(), modified)?)
+    }
+
+    fn symlink<P: AsRef<Path>>(&self, target: P, link: P) -> Result<(), Self::FSError> {
+        let link = VirtualPath::new(link)?;
+        if self.merged_metadata(&link).is_some() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, link.to_string()).into());
+        }
+        self.create_upper_parent(&link)?;
+        self.upper
+            .symlink(PathBuf::from(target.as_ref()), link.to_path_buf())?;
+        self.unwhiteout(link.relative());
+
+        Ok(())
+    }
+
+    fn read_link<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Self::FSError> {
+        let path = VirtualPath::new(path)?;
+        match self.layer(&path)? {
+            Layer::Upper => Ok(self.upper.read_link(path.to_path_buf())?),
+            Layer::Lower => Ok(self.lower.read_link(path.to_path_buf())?),
+        }
+    }
+
+    fn symlink_metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata, Self::FSError> {
+        let path = VirtualPath::new(path)?;
+        self.merged_metadata(&path).ok_or(ChiconError::BadPath)
+    }
+
+    fn canonicalize<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Self::FSError> {
+        let path = VirtualPath::new(path)?;
+        match self.layer(&path)? {
+            Layer::Upper => Ok(self.upper.canonicalize(path.to_path_buf())?),
+            Layer::Lower => Ok(self.lower.canonicalize(path.to_path_buf())?),
+        }
+    }
+
+    fn checksum<P: AsRef<Path>>(
+        &self,
+        path: P,
+        algorithm: ChecksumAlgorithm,
+    ) -> Result<String, Self::FSError> {
+        let path = VirtualPath::new(path)?;
+        match self.layer(&path)? {
+            Layer::Upper => Ok(self.upper.checksum(path.to_path_buf(), algorithm)?),
+            Layer::Lower => Ok(self.lower.checksum(path.to_path_buf(), algorithm)?),
+        }
+    }
+}
+
+/// Structure implementing `File` trait to represent a file of an `OverlayFileSystem`,
+/// files of the lower layer can't be written
+pub enum OverlayFile<U: File, L: File> {
+    Upper(U),
+    Lower(L),
+}
+
+impl<U, L> File for OverlayFile<U, L>
+where
+    U: File,
+    L: File,
+    ChiconError: From<U::FSError>,
+{
+    type FSError = ChiconError;
+
+    fn sync_all(&mut self) -> Result<(), Self::FSError> {
+        match self {
+            OverlayFile::Upper(file) => Ok(file.sync_all()?),
+            OverlayFile::Lower(_) => Ok(()),
+        }
+    }
+}
+
+impl<U: File, L: File> Read for OverlayFile<U, L> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            OverlayFile::Upper(file) => file.read(buf),
+            OverlayFile::Lower(file) => file.read(buf),
+        }
+    }
+}
+
+impl<U: File, L: File> Write for OverlayFile<U, L> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            OverlayFile::Upper(file) => file.write(buf),
+            OverlayFile::Lower(_) => Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "file of the lower layer, copy it up before writing",
+            )),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            OverlayFile::Upper(file) => file.flush(),
+            OverlayFile::Lower(_) => Ok(()),
+        }
+    }
+}
+
+impl<U: File, L: File> Seek for OverlayFile<U, L> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            OverlayFile::Upper(file) => file.seek(pos),
+            OverlayFile::Lower(file) => file.seek(pos),
+        }
+    }
+}
+
+/// Structure implementing `DirEntry` trait to represent an entry of the merged view of an `OverlayFileSystem`
+pub struct OverlayDirEntry {
+    path: PathBuf,
+    file_type: FileType,
+}
+
+impl DirEntry for OverlayDirEntry {
+    type FSError = ChiconError;
+
+    fn path(&self) -> Result<PathBuf, Self::FSError> {
+        Ok(self.path.clone())
+    }
+
+    fn file_type(&self) -> Result<FileType, Self::FSError> {
+        Ok(self.file_type.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MemFileSystem, ReadOnly};
+
+    fn lower_fs() -> ReadOnly<MemFileSystem> {
+        let mem_fs = MemFileSystem::new();
+        mem_fs.create_dir_all("share/test").unwrap();
+        {
+            let mut file = mem_fs.create_file("share/test/myfile").unwrap();
+            file.write_all(b"coucoutoi").unwrap();
+        }
+        mem_fs.create_file("share/test/other").unwrap();
+
+        ReadOnly::new(mem_fs)
+    }
+
+    fn names(fs: &OverlayFileSystem<MemFileSystem, ReadOnly<MemFileSystem>>) -> Vec<String> {
+        let mut names: Vec<String> = fs
+            .read_dir("share/test")
+            .unwrap()
+            .iter()
+            .map(|entry| entry.name().unwrap())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_overlay_read_write() {
+        let overlay_fs = OverlayFileSystem::new(MemFileSystem::new(), lower_fs());
+
+        let mut content = String::new();
+        let mut file = overlay_fs.open_file("share/test/myfile").unwrap();
+        file.read_to_string(&mut content).unwrap();
+        assert_eq!(String::from("coucoutoi"), content);
+        assert!(file.write_all(b"nope").is_err());
+
+        {
+            let mut file = overlay_fs.create_file("share/test/myfile").unwrap();
+            file.write_all(b"updated").unwrap();
+        }
+        overlay_fs.create_file("share/test/new").unwrap();
+        let mut content = String::new();
+        overlay_fs
+            .open_file("share/test/myfile")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(String::from("updated"), content);
+        assert_eq!(names(&overlay_fs), vec!["myfile", "new", "other"]);
+
+        let (_, lower) = overlay_fs.into_inner();
+        let mut content = String::new();
+        lower
+            .open_file("share/test/myfile")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(String::from("coucoutoi"), content);
+    }
+
+    #[test]
+    fn test_overlay_whiteout() {
+        let overlay_fs = OverlayFileSystem::new(MemFileSystem::new(), lower_fs());
+
+        overlay_fs.remove_file("share/test/other").unwrap();
+        assert!(overlay_fs.metadata("share/test/other").is_err());
+        assert_eq!(names(&overlay_fs), vec!["myfile"]);
+
+        overlay_fs
+            .rename("share/test/myfile", "share/test/renamed")
+            .unwrap();
+        assert_eq!(names(&overlay_fs), vec!["renamed"]);
+
+        overlay_fs.remove_dir_all("share/test").unwrap();
+        assert!(overlay_fs.read_dir("share/test").is_err());
+        overlay_fs.create_dir("share/test").unwrap();
+        assert!(overlay_fs.read_dir("share/test").unwrap().is_empty());
+        assert!(overlay_fs.metadata("share/test/other").is_err());
+    }
+}
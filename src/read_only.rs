@@ -0,0 +1,216 @@
+use std::fs::Permissions;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::{ChecksumAlgorithm, File, FileSystem, Metadata};
+
+fn read_only_error() -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, "read-only filesystem")
+}
+
+/// Structure implementing `FileSystem` trait to forbid any modification of the wrapped filesystem.
+///
+/// Read operations are forwarded, any write operation returns a `PermissionDenied` IO error,
+/// including writes and `sync_all` on opened files.
+pub struct ReadOnly<F: FileSystem> {
+    inner: F,
+}
+
+impl<F: FileSystem> ReadOnly<F> {
+    pub fn new(inner: F) -> Self {
+        ReadOnly { inner }
+    }
+
+    /// Get back the wrapped filesystem
+    pub fn into_inner(self) -> F {
+        self.inner
+    }
+}
+
+impl<F: FileSystem> FileSystem for ReadOnly<F> {
+    type FSError = F::FSError;
+    type File = ReadOnlyFile<F::File>;
+    type DirEntry = F::DirEntry;
+
+    fn chmod<P: AsRef<Path>>(&self, _path: P, _perm: Permissions) -> Result<(), Self::FSError> {
+        Err(read_only_error().into())
+    }
+
+    fn create_file<P: AsRef<Path>>(&self, _path: P) -> Result<Self::File, Self::FSError> {
+        Err(read_only_error().into())
+    }
+
+    fn create_dir<P: AsRef<Path>>(&self, _path: P) -> Result<(), Self::FSError> {
+        Err(read_only_error().into())
+    }
+
+    fn create_dir_all<P: AsRef<Path>>(&self, _path: P) -> Result<(), Self::FSError> {
+        Err(read_only_error().into())
+    }
+
+    fn open_file<P: AsRef<Path>>(&self, path: P) -> Result<Self::File, Self::FSError> {
+        Ok(ReadOnlyFile {
+            inner: self.inner.open_file(path)?,
+        })
+    }
+
+    fn read_dir<P: AsRef<Path>>(&self, path: P) -> Result<Vec<Self::DirEntry>, Self::FSError> {
+        self.inner.read_dir(path)
+    }
+
+    fn remove_file<P: AsRef<Path>>(&self, _path: P) -> Result<(), Self::FSError> {
+        Err(read_only_error().into())
+    }
+
+    fn remove_dir<P: AsRef<Path>>(&self, _path: P) -> Result<(), Self::FSError> {
+        Err(read_only_error().into())
+    }
+
+    fn remove_dir_all<P: AsRef<Path>>(&self, _path: P) -> Result<(), Self::FSError> {
+        Err(read_only_error().into())
+    }
+
+    fn rename<P: AsRef<Path>>(&self, _from: P, _to: P) -> Result<(), Self::FSError> {
+        Err(read_only_error().into())
+    }
+
+    fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata, Self::FSError> {
+        self.inner.metadata(path)
+    }
+
+    fn symlink<P: AsRef<Path>>(&self, _target: P, _link: P) -> Result<(), Self::FSError> {
+        Err(read_only_error().into())
+    }
+
+    fn read_link<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Self::FSError> {
+        self.inner.read_link(path)
+    }
+
+    fn symlink_metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata, Self::FSError> {
+        self.inner.symlink_metadata(path)
+    }
+
+    fn canonicalize<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Self::FSError> {
+        self.inner.canonicalize(path)
+    }
+
+    fn set_modified<P: AsRef<Path>>(
+        &self,
+        _path: P,
+        _modified: SystemTime,
+    ) -> Result<(), Self::FSError> {
+        Err(read_only_error().into())
+    }
+
+    fn copy<P: AsRef<Path>>(&self, _from: P, _to: P) -> Result<(), Self::FSError> {
+        Err(read_only_error().into())
+    }
+
+    fn copy_dir_all<P: AsRef<Path>>(&self, _from: P, _to: P) -> Result<(), Self::FSError> {
+        Err(read_only_error().into())
+    }
+
+    fn checksum<P: AsRef<Path>>(
+        &self,
+        path: P,
+        algorithm: ChecksumAlgorithm,
+    ) -> Result<String, Self::FSError> {
+        self.inner.checksum(path, algorithm)
+    }
+
+    fn atomic_write<P, W>(&self, _path: P, _write: W) -> Result<(), Self::FSError>
+    where
+        P: AsRef<Path>,
+        W: FnOnce(&mut Self::File) -> Result<(), Self::FSError>,
+    {
+        Err(read_only_error().into())
+    }
+}
+
+/// Structure implementing `File` trait to represent a file opened through `ReadOnly`, it can only be read
+pub struct ReadOnlyFile<T: File> {
+    inner: T,
+}
+
+impl<T: File> File for ReadOnlyFile<T> {
+    type FSError = io::Error;
+
+    fn sync_all(&mut self) -> Result<(), Self::FSError> {
+        Err(read_only_error())
+    }
+}
+
+impl<T: File> Read for ReadOnlyFile<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<T: File> Write for ReadOnlyFile<T> {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(read_only_error())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<T: File> Seek for ReadOnlyFile<T> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ChiconError;
+    use crate::MemFileSystem;
+
+    fn is_permission_denied(err: ChiconError) -> bool {
+        match err {
+            ChiconError::IOError(err) => err.kind() == io::ErrorKind::PermissionDenied,
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn test_read_only() {
+        let mem_fs = MemFileSystem::new();
+        mem_fs.create_dir_all("share/test").unwrap();
+        {
+            let mut file = mem_fs.create_file("share/test/myfile").unwrap();
+            file.write_all(b"coucoutoi").unwrap();
+        }
+        let ro_fs = ReadOnly::new(mem_fs);
+
+        let mut file = ro_fs.open_file("share/test/myfile").unwrap();
+        let mut content = String::new();
+        file.read_to_string(&mut content).unwrap();
+        assert_eq!(String::from("coucoutoi"), content);
+        assert_eq!(
+            file.write(b"other").unwrap_err().kind(),
+            io::ErrorKind::PermissionDenied
+        );
+        assert_eq!(
+            file.sync_all().unwrap_err().kind(),
+            io::ErrorKind::PermissionDenied
+        );
+
+        assert_eq!(ro_fs.read_dir("share/test").unwrap().len(), 1);
+        assert!(ro_fs.metadata("share/test/myfile").is_ok());
+        assert!(is_permission_denied(
+            ro_fs.create_file("share/test/other").err().unwrap()
+        ));
+        assert!(is_permission_denied(
+            ro_fs.remove_dir_all("share").unwrap_err()
+        ));
+        assert!(is_permission_denied(
+            ro_fs
+                .rename("share/test/myfile", "share/test/other")
+                .unwrap_err()
+        ));
+    }
+}
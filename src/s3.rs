@@ -2,22 +2,36 @@ use std::env;
 use std::fs::Permissions;
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 use url::percent_encoding::{utf8_percent_encode, SIMPLE_ENCODE_SET};
 
 use rusoto_core::{
-    credential::EnvironmentProvider, region::Region, request::HttpClient, ByteStream,
+    credential::EnvironmentProvider, region::Region, request::HttpClient, ByteStream, RusotoError,
 };
 use rusoto_s3::{
-    CopyObjectRequest, Delete, DeleteObjectRequest, DeleteObjectsRequest, GetObjectRequest,
-    ListObjectsV2Request, ObjectIdentifier, PutObjectRequest, S3Client, S3,
+    AbortMultipartUploadRequest, CompleteMultipartUploadRequest, CompletedMultipartUpload,
+    CompletedPart, CopyObjectRequest, CreateMultipartUploadError, CreateMultipartUploadRequest,
+    Delete, DeleteObjectRequest, DeleteObjectsRequest, GetObjectRequest, HeadObjectRequest,
+    ListObjectsV2Request, ObjectIdentifier, PutObjectRequest, S3Client, UploadPartCopyRequest, S3,
 };
 
-use crate::{error::ChiconError, DirEntry, File, FileSystem, FileType};
+use chrono::DateTime;
+
+use crate::checksum::hash_reader;
+use crate::{
+    error::ChiconError, ChecksumAlgorithm, DirEntry, File, FileSystem, FileType, Metadata,
+    VirtualPath,
+};
 
 define_encode_set! {
     pub QUERY_ENCODE_SET = [SIMPLE_ENCODE_SET] | {' ', '"', '#', '<', '>'}
 }
 
+// Objects bigger than this limit can't be copied with a single CopyObject request
+const MAX_COPY_OBJECT_SIZE: u64 = 5 * 1024 * 1024 * 1024;
+const MULTIPART_COPY_PART_SIZE: u64 = 512 * 1024 * 1024;
+const MAX_DELETE_OBJECTS: usize = 1000;
+
 /// Structure implementing `FileSystem` trait to store on an Amazon S3 API compliant
 pub struct S3FileSystem {
     bucket: String,
@@ -44,6 +58,128 @@ impl S3FileSystem {
         );
         S3FileSystem { bucket, s3_client }
     }
+
+    fn copy_source(&self, key: &str) -> String {
+        utf8_percent_encode(
+            format!("{}/{}", self.bucket, key).as_ref(),
+            QUERY_ENCODE_SET,
+        )
+        .collect::<String>()
+    }
+
+    // A single ListObjectsV2 response holds at most 1000 keys, follow the continuation token
+    // until every key is listed or `limit` keys are found
+    fn list_keys(
+        &self,
+        prefix: Option<String>,
+        limit: Option<usize>,
+    ) -> Result<Vec<String>, ChiconError> {
+        let mut keys: Vec<String> = Vec::new();
+        let mut continuation_token: Option<String> = None;
+        loop {
+            let list_req = ListObjectsV2Request {
+                bucket: self.bucket.clone(),
+                prefix: prefix.clone(),
+                max_keys: limit.map(|limit| (limit - keys.len()) as i64),
+                continuation_token,
+                ..Default::default()
+            };
+            let list = self.s3_client.list_objects_v2(list_req).sync()?;
+            keys.extend(
+                list.contents
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter_map(|object| object.key),
+            );
+
+            let limit_reached = limit.is_some_and(|limit| keys.len() >= limit);
+            match list.next_continuation_token {
+                Some(token) if list.is_truncated == Some(true) && !limit_reached => {
+                    continuation_token = Some(token)
+                }
+                _ => return Ok(keys),
+            }
+        }
+    }
+
+    fn multipart_copy(&self, from: &str, to: &str, size: u64) -> Result<(), ChiconError> {
+        let create_req = CreateMultipartUploadRequest {
+            bucket: self.bucket.clone(),
+            key: to.to_string(),
+            ..Default::default()
+        };
+        let upload_id = self
+            .s3_client
+            .create_multipart_upload(create_req)
+            .sync()?
+            .upload_id
+            .ok_or_else(|| {
+                RusotoError::<CreateMultipartUploadError>::ParseError(String::from(
+                    "missing upload id in response",
+                ))
+            })?;
+
+        match self.upload_parts_copy(from, to, size, &upload_id) {
+            Ok(parts) => {
+                let complete_req = CompleteMultipartUploadRequest {
+                    bucket: self.bucket.clone(),
+                    key: to.to_string(),
+                    multipart_upload: Some(CompletedMultipartUpload { parts: Some(parts) }),
+                    upload_id,
+                    ..Default::default()
+                };
+                self.s3_client
+                    .complete_multipart_upload(complete_req)
+                    .sync()
+                    .map(|_| ())
+                    .map_err(ChiconError::from)
+            }
+            Err(err) => {
+                let abort_req = AbortMultipartUploadRequest {
+                    bucket: self.bucket.clone(),
+                    key: to.to_string(),
+                    upload_id,
+                    ..Default::default()
+                };
+                self.s3_client.abort_multipart_upload(abort_req).sync()?;
+                Err(err)
+            }
+        }
+    }
+
+    fn upload_parts_copy(
+        &self,
+        from: &str,
+        to: &str,
+        size: u64,
+        upload_id: &str,
+    ) -> Result<Vec<CompletedPart>, ChiconError> {
+        let mut parts: Vec<CompletedPart> = Vec::new();
+        let mut start: u64 = 0;
+        let mut part_number: i64 = 1;
+        while start < size {
+            let end = std::cmp::min(start + MULTIPART_COPY_PART_SIZE, size) - 1;
+            let part_req = UploadPartCopyRequest {
+                bucket: self.bucket.clone(),
+                key: to.to_string(),
+                copy_source: self.copy_source(from),
+                copy_source_range: Some(format!("bytes={}-{}", start, end)),
+                part_number,
+                upload_id: upload_id.to_string(),
+                ..Default::default()
+            };
+            let part_res = self.s3_client.upload_part_copy(part_req).sync()?;
+            parts.push(CompletedPart {
+                e_tag: part_res.copy_part_result.and_then(|res| res.e_tag),
+                part_number: Some(part_number),
+            });
+
+            start = end + 1;
+            part_number += 1;
+        }
+
+        Ok(parts)
+    }
 }
 impl FileSystem for S3FileSystem {
     type FSError = ChiconError;
@@ -51,15 +187,11 @@ impl FileSystem for S3FileSystem {
     type DirEntry = S3DirEntry;
 
     fn chmod<P: AsRef<Path>>(&self, _path: P, _perm: Permissions) -> Result<(), Self::FSError> {
-        unimplemented!()
+        Err(ChiconError::Unsupported(String::from("chmod")))
     }
 
     fn create_file<P: AsRef<Path>>(&self, path: P) -> Result<Self::File, Self::FSError> {
-        let path: &Path = path.as_ref();
-        let filename: String = path.to_string_lossy().into_owned();
-        if filename.contains("../") {
-            return Err(ChiconError::RelativePath);
-        }
+        let filename: String = VirtualPath::new(path)?.to_key();
         let req = PutObjectRequest {
             bucket: self.bucket.clone(),
             key: filename.clone(),
@@ -82,14 +214,7 @@ impl FileSystem for S3FileSystem {
     }
 
     fn create_dir<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
-        let path: &Path = path.as_ref();
-        let mut dir: String = path.to_string_lossy().into_owned();
-        if dir.contains("../") {
-            return Err(ChiconError::RelativePath);
-        }
-        if !dir.ends_with('/') {
-            dir.push('/');
-        }
+        let dir: String = VirtualPath::new(path)?.to_dir_key();
 
         let req = PutObjectRequest {
             bucket: self.bucket.clone(),
@@ -110,11 +235,7 @@ impl FileSystem for S3FileSystem {
     }
 
     fn open_file<P: AsRef<Path>>(&self, path: P) -> Result<Self::File, Self::FSError> {
-        let path: &Path = path.as_ref();
-        let filename: String = path.to_string_lossy().into_owned();
-        if filename.contains("../") {
-            return Err(ChiconError::RelativePath);
-        }
+        let filename: String = VirtualPath::new(path)?.to_key();
         let get_req = GetObjectRequest {
             bucket: self.bucket.clone(),
             key: filename.clone(),
@@ -131,47 +252,19 @@ impl FileSystem for S3FileSystem {
     }
 
     fn read_dir<P: AsRef<Path>>(&self, path: P) -> Result<Vec<Self::DirEntry>, Self::FSError> {
-        let path: &Path = path.as_ref();
-        let mut dir_name: String = path
-            .to_string_lossy()
-            .into_owned()
-            .trim_start_matches("./")
-            .to_string();
-        if dir_name.contains("../") {
-            return Err(ChiconError::RelativePath);
-        }
-        let prefix: Option<String> = if dir_name != "." {
-            if !dir_name.ends_with('/') {
-                dir_name.push('/');
-            }
-            Some(dir_name.clone())
+        let dir_name: String = VirtualPath::new(path)?.to_dir_key();
+        let prefix: Option<String> = if !dir_name.is_empty() {
+            Some(dir_name)
         } else {
             None
         };
 
-        let list_req = ListObjectsV2Request {
-            bucket: self.bucket.clone(),
-            prefix,
-            ..Default::default()
-        };
-        let list = self.s3_client.list_objects_v2(list_req).sync()?;
-        let mut dir_entries: Vec<S3DirEntry> = Vec::new();
-        if let Some(objects) = list.contents {
-            for object in objects {
-                if let Some(key) = object.key {
-                    dir_entries.push(S3DirEntry { key });
-                }
-            }
-        }
-        Ok(dir_entries)
+        let keys = self.list_keys(prefix, None)?;
+        Ok(keys.into_iter().map(|key| S3DirEntry { key }).collect())
     }
 
     fn remove_file<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
-        let path: &Path = path.as_ref();
-        let filename = path.to_string_lossy().into_owned();
-        if filename.contains("../") {
-            return Err(ChiconError::RelativePath);
-        }
+        let filename: String = VirtualPath::new(path)?.to_key();
         let req = DeleteObjectRequest {
             bucket: self.bucket.clone(),
             key: filename,
@@ -186,20 +279,17 @@ impl FileSystem for S3FileSystem {
     }
 
     fn remove_dir<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
-        let path: &Path = path.as_ref();
-        let dir_name = path.to_string_lossy().into_owned();
-        if dir_name.contains("../") {
-            return Err(ChiconError::RelativePath);
-        }
+        let dir_name: String = VirtualPath::new(path)?.to_dir_key();
 
-        let dir_entries = self.read_dir(path)?;
-        if !dir_entries.is_empty() {
+        // The directory object itself is listed with its prefix
+        let dir_entries = self.read_dir(dir_name.as_str())?;
+        if dir_entries.iter().any(|entry| entry.key != dir_name) {
             return Err(ChiconError::DirectoryNotEmpty);
         }
 
         let req = DeleteObjectRequest {
             bucket: self.bucket.clone(),
-            key: path.to_string_lossy().into_owned(),
+            key: dir_name,
             ..Default::default()
         };
 
@@ -211,65 +301,213 @@ impl FileSystem for S3FileSystem {
     }
 
     fn remove_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
-        let path: &Path = path.as_ref();
-        let dir_name = path.to_string_lossy().into_owned();
-        if dir_name.contains("../") {
-            return Err(ChiconError::RelativePath);
-        }
+        let dir_name: String = VirtualPath::new(path)?.to_dir_key();
 
-        let dir_entries = self.read_dir(path)?;
+        let dir_entries = self.read_dir(dir_name.as_str())?;
         if dir_entries.is_empty() {
-            return self.remove_dir(path);
+            return self.remove_dir(dir_name.as_str());
+        }
+
+        // A DeleteObjects request takes at most MAX_DELETE_OBJECTS keys
+        for chunk in dir_entries.chunks(MAX_DELETE_OBJECTS) {
+            let elt_to_delete: Vec<ObjectIdentifier> = chunk
+                .iter()
+                .map(|elt| ObjectIdentifier {
+                    key: elt.key.clone(),
+                    version_id: None,
+                })
+                .collect();
+            let req = DeleteObjectsRequest {
+                bucket: self.bucket.clone(),
+                delete: Delete {
+                    objects: elt_to_delete,
+                    quiet: None,
+                },
+                ..Default::default()
+            };
+            self.s3_client.delete_objects(req).sync()?;
         }
 
-        let mut elt_to_delete: Vec<ObjectIdentifier> = Vec::with_capacity(dir_entries.len());
-        for elt in dir_entries {
-            elt_to_delete.push(ObjectIdentifier {
-                key: elt.path()?.to_string_lossy().into_owned(),
-                version_id: None,
+        Ok(())
+    }
+
+    fn rename<P: AsRef<Path>>(&self, from: P, to: P) -> Result<(), Self::FSError> {
+        let from: &Path = from.as_ref();
+        self.copy(from, to.as_ref())?;
+        self.remove_file(from)
+    }
+
+    fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata, Self::FSError> {
+        let filename: String = VirtualPath::new(path)?.to_key();
+        if filename.is_empty() {
+            return Ok(Metadata {
+                file_type: FileType::Directory,
+                len: 0,
+                modified: None,
+                permissions: None,
+                etag: None,
             });
         }
-        let req = DeleteObjectsRequest {
+
+        let head_req = HeadObjectRequest {
             bucket: self.bucket.clone(),
-            delete: Delete {
-                objects: elt_to_delete,
-                quiet: None,
-            },
+            key: filename.clone(),
             ..Default::default()
         };
+        let head_err = match self.s3_client.head_object(head_req).sync() {
+            Ok(object) => {
+                return Ok(Metadata {
+                    file_type: FileType::File,
+                    len: object.content_length.unwrap_or(0) as u64,
+                    modified: object
+                        .last_modified
+                        .and_then(|date| DateTime::parse_from_rfc2822(&date).ok())
+                        .map(SystemTime::from),
+                    permissions: None,
+                    etag: object.e_tag,
+                });
+            }
+            Err(err) => err,
+        };
 
-        self.s3_client
-            .delete_objects(req)
-            .sync()
-            .map(|_| ())
-            .map_err(ChiconError::from)
+        // There is no object with this key, it can still be a prefix of other objects
+        let keys = self.list_keys(Some(format!("{}/", filename)), Some(1))?;
+        if !keys.is_empty() {
+            Ok(Metadata {
+                file_type: FileType::Directory,
+                len: 0,
+                modified: None,
+                permissions: None,
+                etag: None,
+            })
+        } else {
+            Err(ChiconError::from(head_err))
+        }
     }
 
-    fn rename<P: AsRef<Path>>(&self, from: P, to: P) -> Result<(), Self::FSError> {
-        let from: &Path = from.as_ref();
-        let from_filename: String = from.to_string_lossy().into_owned();
-        if from_filename.contains("../") {
-            return Err(ChiconError::RelativePath);
+    /// S3 sets the modification time of an object when it is written
+    fn set_modified<P: AsRef<Path>>(
+        &self,
+        _path: P,
+        _modified: SystemTime,
+    ) -> Result<(), Self::FSError> {
+        Err(ChiconError::Unsupported(String::from("set_modified")))
+    }
+
+    fn symlink<P: AsRef<Path>>(&self, _target: P, _link: P) -> Result<(), Self::FSError> {
+        Err(ChiconError::Unsupported(String::from("symlink")))
+    }
+
+    fn read_link<P: AsRef<Path>>(&self, _path: P) -> Result<PathBuf, Self::FSError> {
+        Err(ChiconError::Unsupported(String::from("read_link")))
+    }
+
+    /// S3 has no symbolic links, this is the same as `metadata`
+    fn symlink_metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata, Self::FSError> {
+        self.metadata(path)
+    }
+
+    /// S3 has no symbolic links, `.` and `..` are resolved lexically without checking the object exists
+    fn canonicalize<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Self::FSError> {
+        Ok(PathBuf::from(VirtualPath::new(path)?.relative()))
+    }
+
+    /// The object is uploaded with a single PUT once `write` succeeds, S3 never exposes partial objects
+    /// so no temporary object is needed.
+    fn atomic_write<P, F>(&self, path: P, write: F) -> Result<(), Self::FSError>
+    where
+        P: AsRef<Path>,
+        F: FnOnce(&mut Self::File) -> Result<(), Self::FSError>,
+    {
+        let filename: String = VirtualPath::new(path)?.to_key();
+
+        let mut file = S3File::new(self.bucket.clone(), filename, self.s3_client.clone());
+        write(&mut file)?;
+        file.sync_all()
+    }
+
+    fn checksum<P: AsRef<Path>>(
+        &self,
+        path: P,
+        algorithm: ChecksumAlgorithm,
+    ) -> Result<String, Self::FSError> {
+        let filename: String = VirtualPath::new(path)?.to_key();
+
+        if algorithm == ChecksumAlgorithm::Md5 {
+            let head_req = HeadObjectRequest {
+                bucket: self.bucket.clone(),
+                key: filename.clone(),
+                ..Default::default()
+            };
+            let object = self.s3_client.head_object(head_req).sync()?;
+            // The ETag is the MD5 of the content only for single part objects not encrypted with KMS
+            if let Some(etag) = object.e_tag {
+                let etag = etag.trim_matches('"');
+                let kms_encrypted = object.server_side_encryption == Some(String::from("aws:kms"));
+                if !etag.contains('-') && !kms_encrypted {
+                    return Ok(etag.to_lowercase());
+                }
+            }
         }
-        let to: &Path = to.as_ref();
-        let to_filename: String = to.to_string_lossy().into_owned();
-        if to_filename.contains("../") {
-            return Err(ChiconError::RelativePath);
+
+        let mut file = self.open_file(filename)?;
+        hash_reader(&mut file, algorithm).map_err(ChiconError::from)
+    }
+
+    fn copy<P: AsRef<Path>>(&self, from: P, to: P) -> Result<(), Self::FSError> {
+        let from_filename: String = VirtualPath::new(from)?.to_key();
+        let to_filename: String = VirtualPath::new(to)?.to_key();
+
+        let head_req = HeadObjectRequest {
+            bucket: self.bucket.clone(),
+            key: from_filename.clone(),
+            ..Default::default()
+        };
+        let size = self
+            .s3_client
+            .head_object(head_req)
+            .sync()?
+            .content_length
+            .unwrap_or(0) as u64;
+        if size > MAX_COPY_OBJECT_SIZE {
+            return self.multipart_copy(&from_filename, &to_filename, size);
         }
 
         let copy_req = CopyObjectRequest {
             bucket: self.bucket.clone(),
             key: to_filename,
-            copy_source: utf8_percent_encode(
-                format!("{}/{}", self.bucket, from_filename).as_ref(),
-                QUERY_ENCODE_SET,
-            )
-            .collect::<String>(),
+            copy_source: self.copy_source(&from_filename),
             ..Default::default()
         };
 
-        self.s3_client.copy_object(copy_req).sync()?;
-        self.remove_file(from_filename)
+        self.s3_client
+            .copy_object(copy_req)
+            .sync()
+            .map(|_| ())
+            .map_err(ChiconError::from)
+    }
+
+    fn copy_dir_all<P: AsRef<Path>>(&self, from: P, to: P) -> Result<(), Self::FSError> {
+        let from_prefix: String = VirtualPath::new(from)?.to_dir_key();
+        let to_prefix: String = VirtualPath::new(to)?.to_dir_key();
+
+        self.create_dir(to_prefix.as_str())?;
+        for entry in self.read_dir(from_prefix.as_str())? {
+            let suffix = entry
+                .key
+                .strip_prefix(from_prefix.as_str())
+                .unwrap_or(&entry.key);
+            if suffix.is_empty() {
+                continue;
+            }
+            if entry.key.ends_with('/') {
+                self.create_dir(format!("{}{}", to_prefix, suffix))?;
+            } else {
+                self.copy(entry.key.clone(), format!("{}{}", to_prefix, suffix))?;
+            }
+        }
+
+        Ok(())
     }
 }
 
@@ -376,7 +614,7 @@ impl DirEntry for S3DirEntry {
     type FSError = ChiconError;
 
     fn path(&self) -> Result<PathBuf, Self::FSError> {
-        Ok(PathBuf::from(self.key.clone()))
+        Ok(VirtualPath::from_key(&self.key))
     }
 
     fn file_type(&self) -> Result<FileType, Self::FSError> {
@@ -462,6 +700,179 @@ mod tests {
         s3_fs.remove_dir_all("test/testrenamebis.test").unwrap();
     }
 
+    #[test]
+    fn test_copy_file() {
+        let s3_fs = S3FileSystem::new(
+            String::from("testest"),
+            String::from("testtest"),
+            String::from("test"),
+            String::from("local"),
+            String::from("http://127.0.0.1"),
+        );
+        let mut file = s3_fs.create_file("test/testcopy.test").unwrap();
+
+        file.write_all(String::from("coucoutoi").as_bytes())
+            .unwrap();
+        file.sync_all().unwrap();
+
+        s3_fs
+            .copy("test/testcopy.test", "test/testcopybis.test")
+            .unwrap();
+
+        let mut file_copied = s3_fs.open_file("test/testcopybis.test").unwrap();
+        let mut content: String = String::new();
+        file_copied.read_to_string(&mut content).unwrap();
+        assert_eq!(content, String::from("coucoutoi"));
+        assert!(s3_fs.open_file("test/testcopy.test").is_ok());
+
+        s3_fs.remove_dir_all("test").unwrap();
+    }
+
+    #[test]
+    fn test_atomic_write() {
+        let s3_fs = S3FileSystem::new(
+            String::from("testest"),
+            String::from("testtest"),
+            String::from("test"),
+            String::from("local"),
+            String::from("http://127.0.0.1"),
+        );
+
+        s3_fs
+            .atomic_write("testatomic.test", |file| {
+                file.write_all(b"coucoutoi")?;
+                Ok(())
+            })
+            .unwrap();
+        let res = s3_fs.atomic_write("testatomicbis.test", |_| Err(ChiconError::BadPath));
+
+        assert!(res.is_err());
+        assert!(s3_fs.open_file("testatomicbis.test").is_err());
+        let mut content: String = String::new();
+        s3_fs
+            .open_file("testatomic.test")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, String::from("coucoutoi"));
+
+        s3_fs.remove_file("testatomic.test").unwrap();
+    }
+
+    #[test]
+    fn test_copy_dir_all() {
+        let s3_fs = S3FileSystem::new(
+            String::from("testest"),
+            String::from("testtest"),
+            String::from("test"),
+            String::from("local"),
+            String::from("http://127.0.0.1"),
+        );
+        s3_fs.create_dir_all("testcopydir/test").unwrap();
+        s3_fs.create_file("testcopydir/test/mytest.test").unwrap();
+
+        s3_fs.copy_dir_all("testcopydir", "testcopydirbis").unwrap();
+
+        assert!(s3_fs.open_file("testcopydirbis/test/mytest.test").is_ok());
+
+        s3_fs.remove_dir_all("testcopydir").unwrap();
+        s3_fs.remove_dir_all("testcopydirbis").unwrap();
+    }
+
+    #[test]
+    fn test_symlink_unsupported() {
+        let s3_fs = S3FileSystem::new(
+            String::from("testest"),
+            String::from("testtest"),
+            String::from("test"),
+            String::from("local"),
+            String::from("http://127.0.0.1"),
+        );
+
+        match s3_fs.symlink("test.test", "testlink.test") {
+            Err(ChiconError::Unsupported(_)) => {}
+            _ => panic!("symlink must be unsupported on S3"),
+        }
+        match s3_fs.read_link("testlink.test") {
+            Err(ChiconError::Unsupported(_)) => {}
+            _ => panic!("read_link must be unsupported on S3"),
+        }
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        let s3_fs = S3FileSystem::new(
+            String::from("testest"),
+            String::from("testtest"),
+            String::from("test"),
+            String::from("local"),
+            String::from("http://127.0.0.1"),
+        );
+
+        assert_eq!(
+            s3_fs.canonicalize("test/./other/../test.test").unwrap(),
+            PathBuf::from("test/test.test")
+        );
+        assert!(s3_fs.canonicalize("test/../../test.test").is_err());
+    }
+
+    #[test]
+    fn test_metadata() {
+        let s3_fs = S3FileSystem::new(
+            String::from("testest"),
+            String::from("testtest"),
+            String::from("test"),
+            String::from("local"),
+            String::from("http://127.0.0.1"),
+        );
+        let mut file = s3_fs.create_file("testmetadata/test.test").unwrap();
+
+        file.write_all(String::from("coucoutoi").as_bytes())
+            .unwrap();
+        file.sync_all().unwrap();
+
+        let metadata = s3_fs.metadata("testmetadata/test.test").unwrap();
+        assert!(metadata.is_file());
+        assert_eq!(metadata.len, 9);
+        assert!(metadata.etag.is_some());
+        assert!(s3_fs.metadata("testmetadata").unwrap().is_dir());
+        assert!(s3_fs.metadata("testmetadatanotfound").is_err());
+
+        s3_fs.remove_dir_all("testmetadata").unwrap();
+    }
+
+    #[test]
+    fn test_checksum() {
+        let s3_fs = S3FileSystem::new(
+            String::from("testest"),
+            String::from("testtest"),
+            String::from("test"),
+            String::from("local"),
+            String::from("http://127.0.0.1"),
+        );
+        let mut file = s3_fs.create_file("testchecksum.test").unwrap();
+
+        file.write_all(String::from("coucoutoi").as_bytes())
+            .unwrap();
+        file.sync_all().unwrap();
+
+        assert_eq!(
+            s3_fs
+                .checksum("testchecksum.test", ChecksumAlgorithm::Md5)
+                .unwrap(),
+            String::from("9b7c77a17e4906f5ed3fb8afdc129cfb")
+        );
+        assert_eq!(
+            s3_fs
+                .checksum("testchecksum.test", ChecksumAlgorithm::Crc32c)
+                .unwrap()
+                .len(),
+            8
+        );
+
+        s3_fs.remove_file("testchecksum.test").unwrap();
+    }
+
     #[test]
     fn test_read_dir() {
         let s3_fs = S3FileSystem::new(
@@ -1,53 +1,26 @@
 use std::fs::Permissions;
 use std::io::{Read, Seek, SeekFrom, Write};
-use std::net::TcpStream;
+use std::os::raw::{c_char, c_int};
+use std::os::unix::ffi::OsStrExt;
 use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use ssh2::{FileStat, OpenFlags, Session};
+use ssh2::{ErrorCode, FileStat, Session, Sftp};
 
 use crate::error::ChiconError;
-use crate::{DirEntry, File as FsFile, FileSystem, FileType};
-
-struct SSHSession {
-    // Only useful to not drop connection
-    _tcp_stream: TcpStream,
-    session: Session,
-}
-impl SSHSession {
-    fn new<P: AsRef<Path>>(
-        addr: String,
-        username: &str,
-        passphrase: Option<&str>,
-        private_key: P,
-        public_key: P,
-    ) -> Result<Self, ChiconError> {
-        let private_key = private_key.as_ref();
-        let public_key = public_key.as_ref();
-
-        let tcp_stream = TcpStream::connect(addr)?;
-        let mut session = Session::new().ok_or(ChiconError::SFTPError)?;
-        session.handshake(&tcp_stream)?;
-        session.userauth_pubkey_file(username, Some(public_key), private_key, passphrase)?;
-
-        Ok(SSHSession {
-            _tcp_stream: tcp_stream,
-            session,
-        })
-    }
-
-    fn session(&self) -> &Session {
-        &self.session
-    }
-}
+use crate::session::{HostKeyCheck, JumpHost, SSHAuth, SSHConnection};
+use crate::{temporary_path, DirEntry, File as FsFile, FileSystem, FileType, Metadata};
 
 /// Structure implementing `FileSystem` trait to store on a SFTP server
+///
+/// A single SSH session is opened on first use and shared with the opened files,
+/// it is kept alive and reopened if the connection breaks.
 pub struct SFTPFileSystem<'a> {
-    username: String,
-    addr: String,
-    passphrase: Option<&'a str>,
-    private_key: PathBuf,
-    public_key: PathBuf,
+    connection: Arc<SSHConnection<'a>>,
+    dir_mode: i32,
+    file_mode: i32,
 }
 impl<'a> SFTPFileSystem<'a> {
     pub fn new<P: AsRef<Path>>(
@@ -57,167 +30,244 @@ impl<'a> SFTPFileSystem<'a> {
         private_key: P,
         public_key: P,
     ) -> Self {
-        let private_key = private_key.as_ref();
-        let public_key = public_key.as_ref();
+        Self::with_auth(
+            addr,
+            username,
+            SSHAuth::public_key_file(private_key, public_key, passphrase),
+        )
+    }
 
+    /// Create a filesystem authenticating with any of the `SSHAuth` methods
+    pub fn with_auth(addr: String, username: String, auth: SSHAuth<'a>) -> Self {
         SFTPFileSystem {
-            username,
-            passphrase,
-            private_key: PathBuf::from(private_key),
-            public_key: PathBuf::from(public_key),
-            addr,
+            connection: Arc::new(SSHConnection::new(addr, username, auth)),
+            dir_mode: 0o755,
+            file_mode: 0o755,
         }
     }
+
+    /// Verify the server host key before authenticating, nothing is verified by default
+    pub fn host_key_check(self, host_key_check: HostKeyCheck) -> Self {
+        self.connection.set_host_key_check(host_key_check);
+        self
+    }
+
+    /// Mode of the directories created by `create_dir` and `create_dir_all`, `0o755` by default
+    pub fn dir_mode(mut self, mode: i32) -> Self {
+        self.dir_mode = mode;
+        self
+    }
+
+    /// Mode of the files created on the server, `0o755` by default
+    pub fn file_mode(mut self, mode: i32) -> Self {
+        self.file_mode = mode;
+        self
+    }
+
+    /// Reach the server through `jump_host`, successive jump hosts are gone through in order
+    pub fn jump_host(self, jump_host: JumpHost<'a>) -> Self {
+        self.connection.add_jump_host(jump_host);
+        self
+    }
+
+    /// Connect through the stdin/stdout of `proxy_command` run by `sh`, like `ProxyCommand` of OpenSSH
+    ///
+    /// `%h` and `%p` are replaced by the host and port of the first jump host, or of the server.
+    pub fn proxy_command(self, proxy_command: String) -> Self {
+        self.connection.set_proxy_command(proxy_command);
+        self
+    }
+
+    /// Close the SSH session, the next operation opens a new one
+    pub fn disconnect(&self) -> Result<(), ChiconError> {
+        self.connection.disconnect()
+    }
 }
+// libssh2 implements the posix-rename@openssh.com extension but ssh2 doesn't bind it
+extern "C" {
+    fn libssh2_sftp_posix_rename_ex(
+        sftp: *mut libssh2_sys::LIBSSH2_SFTP,
+        source_filename: *const c_char,
+        source_filename_len: usize,
+        dest_filename: *const c_char,
+        dest_filename_len: usize,
+    ) -> c_int;
+}
+
+// Rename `from` to `to` with the posix-rename@openssh.com extension, which replaces `to` atomically,
+// and return `false` if the server doesn't support it. ssh2 doesn't give access to the SFTP channel of `Sftp`,
+// the request is sent on a channel of its own.
+fn posix_rename(session: &Session, from: &Path, to: &Path) -> Result<bool, ChiconError> {
+    let from = from.as_os_str().as_bytes();
+    let to = to.as_os_str().as_bytes();
+    let res = {
+        let mut raw_session = session.raw();
+        unsafe {
+            let sftp = libssh2_sys::libssh2_sftp_init(&mut *raw_session);
+            if sftp.is_null() {
+                None
+            } else {
+                let rc = libssh2_sftp_posix_rename_ex(
+                    sftp,
+                    from.as_ptr() as *const c_char,
+                    from.len(),
+                    to.as_ptr() as *const c_char,
+                    to.len(),
+                );
+                let errno = libssh2_sys::libssh2_sftp_last_error(sftp) as c_int;
+                libssh2_sys::libssh2_sftp_shutdown(sftp);
+                Some((rc, errno))
+            }
+        }
+    };
+
+    match res {
+        Some((0, _)) => Ok(true),
+        Some((libssh2_sys::LIBSSH2_FX_OP_UNSUPPORTED, _))
+        | Some((
+            libssh2_sys::LIBSSH2_ERROR_SFTP_PROTOCOL,
+            libssh2_sys::LIBSSH2_FX_OP_UNSUPPORTED,
+        )) => Ok(false),
+        Some((libssh2_sys::LIBSSH2_ERROR_SFTP_PROTOCOL, errno)) => {
+            Err(ssh2::Error::from_errno(ErrorCode::SFTP(errno)).into())
+        }
+        Some((rc, _)) => Err(ssh2::Error::from_session_error(session, rc).into()),
+        None => Err(ssh2::Error::last_session_error(session)
+            .unwrap_or_else(ssh2::Error::unknown)
+            .into()),
+    }
+}
+
 impl<'a> FileSystem for SFTPFileSystem<'a> {
     type FSError = ChiconError;
-    type File = SFTPFile<'a>;
+    type File = SFTPStreamFile;
     type DirEntry = SFTPDirEntry;
 
     fn chmod<P: AsRef<Path>>(&self, path: P, perm: Permissions) -> Result<(), Self::FSError> {
-        let path = path.as_ref();
-        let ssh_session = SSHSession::new(
-            self.addr.clone(),
-            &self.username,
-            self.passphrase,
-            self.private_key.as_path(),
-            self.public_key.as_path(),
-        )?;
-        let session = ssh_session.session();
-
-        let sftp = session.sftp()?;
-        sftp.create(path)?;
-
-        let file_stat = sftp.stat(path)?;
-        let stat = FileStat {
-            perm: Some(perm.mode()),
-            ..file_stat
-        };
+        let path = path.as_ref().to_path_buf();
 
-        sftp.setstat(path, stat).map_err(ChiconError::from)
+        self.connection.with_sftp_once(|sftp| {
+            let file_stat = sftp.stat(&path)?;
+            let stat = FileStat {
+                perm: Some(perm.mode()),
+                ..file_stat
+            };
+
+            sftp.setstat(&path, stat).map_err(ChiconError::from)
+        })
     }
 
     fn create_file<P: AsRef<Path>>(&self, path: P) -> Result<Self::File, Self::FSError> {
-        let path = path.as_ref();
-        let ssh_session = SSHSession::new(
-            self.addr.clone(),
-            &self.username,
-            self.passphrase,
-            self.private_key.as_path(),
-            self.public_key.as_path(),
-        )?;
-        let session = ssh_session.session();
-
-        let sftp = session.sftp()?;
-        sftp.create(path)?;
-
-        Ok(SFTPFile::new(
-            PathBuf::from(path),
-            Vec::<u8>::new(),
-            self.addr.clone(),
-            self.username.clone(),
-            self.passphrase,
-            &self.private_key,
-            &self.public_key,
-        ))
+        let path = path.as_ref().to_path_buf();
+
+        let file = self.connection.with_sftp_once(|sftp| {
+            sftp.open_mode(
+                &path,
+                ssh2::OpenFlags::READ
+                    | ssh2::OpenFlags::WRITE
+                    | ssh2::OpenFlags::CREATE
+                    | ssh2::OpenFlags::TRUNCATE,
+                self.file_mode,
+                ssh2::OpenType::File,
+            )
+            .map_err(ChiconError::from)
+        })?;
+
+        Ok(SFTPStreamFile::new(self.connection.sftp()?, file))
     }
 
     fn create_dir<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
-        let ssh_session = SSHSession::new(
-            self.addr.clone(),
-            &self.username,
-            self.passphrase,
-            self.private_key.as_path(),
-            self.public_key.as_path(),
-        )?;
-        let session = ssh_session.session();
-
-        let sftp = session.sftp()?;
-        sftp.mkdir(path.as_ref(), 0o755)
-            .map(|_| ())
-            .map_err(ChiconError::from)
+        let path = path.as_ref().to_path_buf();
+
+        self.connection.with_sftp_once(|sftp| {
+            sftp.mkdir(path.as_path(), self.dir_mode)
+                .map(|_| ())
+                .map_err(ChiconError::from)
+        })
     }
 
     fn create_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
-        self.create_dir(path).map(|_| ()).map_err(ChiconError::from)
+        let path = path.as_ref().to_path_buf();
+
+        self.connection.with_sftp_once(|sftp| {
+            // Ancestors to create, from the deepest one
+            let mut missing = Vec::new();
+            for ancestor in path.ancestors() {
+                if ancestor.as_os_str().is_empty() {
+                    break;
+                }
+                match sftp.stat(ancestor) {
+                    Ok(stat) if stat.is_dir() => break,
+                    Ok(_) => {
+                        return Err(ChiconError::IOError(std::io::Error::new(
+                            std::io::ErrorKind::AlreadyExists,
+                            format!("{} exists and is not a directory", ancestor.display()),
+                        )))
+                    }
+                    Err(_) => missing.push(ancestor),
+                }
+            }
+
+            for dir in missing.into_iter().rev() {
+                if let Err(err) = sftp.mkdir(dir, self.dir_mode) {
+                    // Another client may have created it meanwhile
+                    match sftp.stat(dir) {
+                        Ok(stat) if stat.is_dir() => {}
+                        _ => return Err(ChiconError::from(err)),
+                    }
+                }
+            }
+
+            Ok(())
+        })
     }
 
     fn open_file<P: AsRef<Path>>(&self, path: P) -> Result<Self::File, Self::FSError> {
-        let path = path.as_ref();
-        let ssh_session = SSHSession::new(
-            self.addr.clone(),
-            &self.username,
-            self.passphrase,
-            self.private_key.as_path(),
-            self.public_key.as_path(),
-        )?;
-        let session = ssh_session.session();
-
-        let sftp = session.sftp()?;
-        let mut content: Vec<u8> = Vec::new();
-        {
-            let mut file = sftp.open(path)?;
-            file.read_to_end(&mut content)?;
-        }
-        Ok(SFTPFile::new(
-            PathBuf::from(path),
-            content,
-            self.addr.clone(),
-            self.username.clone(),
-            self.passphrase,
-            &self.private_key,
-            &self.public_key,
-        ))
+        let path = path.as_ref().to_path_buf();
+
+        let file = self.connection.with_sftp(|sftp| {
+            // Files which can't be written are still opened for reading
+            sftp.open_mode(
+                &path,
+                ssh2::OpenFlags::READ | ssh2::OpenFlags::WRITE,
+                0,
+                ssh2::OpenType::File,
+            )
+            .or_else(|_| sftp.open(&path))
+            .map_err(ChiconError::from)
+        })?;
+
+        Ok(SFTPStreamFile::new(self.connection.sftp()?, file))
     }
 
     fn read_dir<P: AsRef<Path>>(&self, path: P) -> Result<Vec<Self::DirEntry>, Self::FSError> {
-        let ssh_session = SSHSession::new(
-            self.addr.clone(),
-            &self.username,
-            self.passphrase,
-            self.private_key.as_path(),
-            self.public_key.as_path(),
-        )?;
-        let session = ssh_session.session();
-
-        let sftp = session.sftp().map_err(ChiconError::from)?;
-        let dir_entries = sftp.readdir(path.as_ref()).map_err(ChiconError::from)?;
+        let path = path.as_ref().to_path_buf();
+
+        let dir_entries = self
+            .connection
+            .with_sftp(|sftp| sftp.readdir(path.as_path()).map_err(ChiconError::from))?;
 
         Ok(dir_entries.into_iter().map(SFTPDirEntry::from).collect())
     }
 
     fn remove_file<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
-        let ssh_session = SSHSession::new(
-            self.addr.clone(),
-            &self.username,
-            self.passphrase,
-            self.private_key.as_path(),
-            self.public_key.as_path(),
-        )?;
-        let session = ssh_session.session();
+        let path = path.as_ref().to_path_buf();
 
-        let sftp = session.sftp().map_err(ChiconError::from)?;
-        sftp.unlink(path.as_ref()).map_err(ChiconError::from)
+        self.connection
+            .with_sftp_once(|sftp| sftp.unlink(path.as_path()).map_err(ChiconError::from))
     }
 
     fn remove_dir<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
-        let ssh_session = SSHSession::new(
-            self.addr.clone(),
-            &self.username,
-            self.passphrase,
-            self.private_key.as_path(),
-            self.public_key.as_path(),
-        )?;
-        let session = ssh_session.session();
-        let sftp = session.sftp().map_err(ChiconError::from)?;
+        let path = path.as_ref().to_path_buf();
 
-        sftp.rmdir(path.as_ref()).map_err(ChiconError::from)
+        self.connection
+            .with_sftp_once(|sftp| sftp.rmdir(path.as_path()).map_err(ChiconError::from))
     }
 
     fn remove_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
-        let path = path.as_ref();
+        let path = path.as_ref().to_path_buf();
 
-        let dir_entries = self.read_dir(path)?;
+        let dir_entries = self.read_dir(&path)?;
         for dir in dir_entries {
             match dir.file_type()? {
                 FileType::Directory => self.remove_dir_all(dir.path()?.as_path())?,
@@ -229,146 +279,264 @@ impl<'a> FileSystem for SFTPFileSystem<'a> {
     }
 
     fn rename<P: AsRef<Path>>(&self, from: P, to: P) -> Result<(), Self::FSError> {
-        let ssh_session = SSHSession::new(
-            self.addr.clone(),
-            &self.username,
-            self.passphrase,
-            self.private_key.as_path(),
-            self.public_key.as_path(),
-        )?;
-        let session = ssh_session.session();
-
-        let sftp = session.sftp().map_err(ChiconError::from)?;
-        sftp.rename(from.as_ref(), to.as_ref(), None)
-            .map_err(ChiconError::from)
+        let from = from.as_ref().to_path_buf();
+        let to = to.as_ref().to_path_buf();
+
+        if self
+            .connection
+            .with_session_once(|session| posix_rename(session, &from, &to))?
+        {
+            return Ok(());
+        }
+
+        self.connection.with_sftp_once(|sftp| {
+            let from = from.as_path();
+            let to = to.as_path();
+            // Without the posix-rename extension SFTPv3 servers refuse to overwrite the destination,
+            // so an existing destination file is moved aside while renaming again and only removed
+            // once `from` took its place
+            let err = match sftp.rename(from, to, None) {
+                Ok(()) => return Ok(()),
+                Err(err) => err,
+            };
+            if sftp.lstat(from).is_err() {
+                return Err(ChiconError::from(err));
+            }
+            match sftp.stat(to) {
+                Ok(ref file_stat) if file_stat.is_file() => {}
+                _ => return Err(ChiconError::from(err)),
+            }
+
+            let aside = temporary_path(to);
+            sftp.rename(to, aside.as_path(), None)?;
+            if let Err(err) = sftp.rename(from, to, None) {
+                sftp.rename(aside.as_path(), to, None)?;
+                return Err(ChiconError::from(err));
+            }
+            sftp.unlink(aside.as_path()).map_err(ChiconError::from)
+        })
+    }
+
+    fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata, Self::FSError> {
+        let path = path.as_ref().to_path_buf();
+
+        let file_stat = self
+            .connection
+            .with_sftp(|sftp| sftp.stat(path.as_path()).map_err(ChiconError::from))?;
+
+        Ok(Metadata::from(file_stat))
+    }
+
+    /// The access time is kept, SFTP only sets both times together
+    fn set_modified<P: AsRef<Path>>(
+        &self,
+        path: P,
+        modified: SystemTime,
+    ) -> Result<(), Self::FSError> {
+        let path = path.as_ref().to_path_buf();
+        let mtime = modified
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+
+        self.connection.with_sftp_once(|sftp| {
+            let file_stat = sftp.stat(&path)?;
+            let stat = FileStat {
+                size: None,
+                uid: None,
+                gid: None,
+                perm: None,
+                atime: file_stat.atime.or(Some(mtime)),
+                mtime: Some(mtime),
+            };
+
+            sftp.setstat(&path, stat).map_err(ChiconError::from)
+        })
+    }
+
+    fn symlink<P: AsRef<Path>>(&self, target: P, link: P) -> Result<(), Self::FSError> {
+        let link = link.as_ref().to_path_buf();
+        let target = target.as_ref();
+
+        self.connection.with_sftp_once(|sftp| {
+            // OpenSSH expects the target before the link path, which is the order libssh2 sends them
+            sftp.symlink(target, link.as_path())
+                .map_err(ChiconError::from)
+        })
+    }
+
+    fn read_link<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Self::FSError> {
+        let path = path.as_ref().to_path_buf();
+
+        self.connection
+            .with_sftp(|sftp| sftp.readlink(path.as_path()).map_err(ChiconError::from))
+    }
+
+    fn symlink_metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata, Self::FSError> {
+        let path = path.as_ref().to_path_buf();
+
+        let file_stat = self
+            .connection
+            .with_sftp(|sftp| sftp.lstat(path.as_path()).map_err(ChiconError::from))?;
+
+        Ok(Metadata::from(file_stat))
+    }
+
+    fn canonicalize<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Self::FSError> {
+        let path = path.as_ref().to_path_buf();
+
+        self.connection
+            .with_sftp(|sftp| sftp.realpath(path.as_path()).map_err(ChiconError::from))
     }
 }
 
+// Size of the chunks read ahead and written at once by `SFTPStreamFile`, libssh2 pipelines
+// the SFTP requests of a single read or write of this size
+const STREAM_CHUNK_SIZE: usize = 256 * 1024;
+
 /// Structure implementing `File` trait to represent a file on a SFTP server
-pub struct SFTPFile<'a> {
-    filename: PathBuf,
-    content: Vec<u8>,
-    addr: String,
-    username: String,
-    passphrase: Option<&'a str>,
-    private_key: PathBuf,
-    public_key: PathBuf,
+///
+/// The file is read and written in place through a handle kept open along it, without holding
+/// its whole content. Reads are done ahead by chunks of 256KB and writes are buffered up to the
+/// same size, `flush` or `sync_all` send the pending writes.
+pub struct SFTPStreamFile {
+    // The SFTP subsystem `file` was opened with, kept open as long as the file
+    _sftp: Arc<Sftp>,
+    file: ssh2::File,
     offset: u64,
-    bytes_read: u64,
+    read_buffer: Vec<u8>,
+    read_offset: u64,
+    write_buffer: Vec<u8>,
+    write_offset: u64,
 }
-impl<'a> SFTPFile<'a> {
-    fn new<P>(
-        filename: PathBuf,
-        content: Vec<u8>,
-        addr: String,
-        username: String,
-        passphrase: Option<&'a str>,
-        private_key: P,
-        public_key: P,
-    ) -> Self
-    where
-        P: AsRef<Path>,
-    {
-        let private_key = private_key.as_ref();
-        let public_key = public_key.as_ref();
-
-        SFTPFile {
-            filename,
-            content,
-            username,
-            passphrase,
-            private_key: PathBuf::from(private_key),
-            public_key: PathBuf::from(public_key),
-            addr,
+impl SFTPStreamFile {
+    fn new(sftp: Arc<Sftp>, file: ssh2::File) -> Self {
+        SFTPStreamFile {
+            _sftp: sftp,
+            file,
             offset: 0,
-            bytes_read: 0,
+            read_buffer: Vec::new(),
+            read_offset: 0,
+            write_buffer: Vec::new(),
+            write_offset: 0,
         }
     }
+
+    fn fetch_chunk(&mut self) -> Result<(), ChiconError> {
+        self.file.seek(SeekFrom::Start(self.offset))?;
+        let mut chunk = Vec::with_capacity(STREAM_CHUNK_SIZE);
+        (&mut self.file)
+            .take(STREAM_CHUNK_SIZE as u64)
+            .read_to_end(&mut chunk)?;
+        self.read_buffer = chunk;
+        self.read_offset = self.offset;
+
+        Ok(())
+    }
+
+    fn write_pending(&mut self) -> Result<(), ChiconError> {
+        if self.write_buffer.is_empty() {
+            return Ok(());
+        }
+        self.file.seek(SeekFrom::Start(self.write_offset))?;
+        self.file.write_all(&self.write_buffer)?;
+        self.write_buffer.clear();
+
+        Ok(())
+    }
+
+    fn len(&mut self) -> Result<u64, ChiconError> {
+        self.write_pending()?;
+        let file_stat = self.file.stat()?;
+
+        Ok(file_stat.size.unwrap_or_default())
+    }
 }
-impl<'a> FsFile for SFTPFile<'a> {
+impl FsFile for SFTPStreamFile {
     type FSError = ChiconError;
 
+    /// Flush the written data and ask the server to sync it with the `fsync@openssh.com` extension,
+    /// servers without it only get the data
     fn sync_all(&mut self) -> Result<(), Self::FSError> {
-        let tcp_stream = TcpStream::connect(self.addr.clone())?;
-        let mut session = Session::new().ok_or(ChiconError::SFTPError)?;
-        session.handshake(&tcp_stream)?;
-        session.userauth_pubkey_file(
-            &self.username,
-            Some(self.public_key.as_path()),
-            self.private_key.as_path(),
-            self.passphrase,
-        )?;
-        let sftp = session.sftp()?;
-
-        // Bits comming from https://docs.rs/libssh2-sys/0.1.33/libssh2_sys/
-        let mut file = sftp.open_mode(
-            &self.filename,
-            OpenFlags::from_bits(2 | 16).unwrap(),
-            0o755,
-            ssh2::OpenType::File,
-        )?;
-        file.write_all(self.content.as_slice())?;
-        file.fsync().map_err(ChiconError::from)
+        self.write_pending()?;
+        match self.file.fsync() {
+            Err(ref err)
+                if err.code() == ErrorCode::SFTP(libssh2_sys::LIBSSH2_FX_OP_UNSUPPORTED) =>
+            {
+                Ok(())
+            }
+            res => res.map_err(ChiconError::from),
+        }
     }
 }
-
-impl<'a> Read for SFTPFile<'a> {
+impl Read for SFTPStreamFile {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
-        let mut content_slice = if self.bytes_read == 0 {
-            if self.offset >= self.content.len() as u64 {
-                return Ok(0);
-            }
-            &self.content[(self.offset as usize)..]
-        } else {
-            self.content.as_slice()
-        };
-        let nb = content_slice.read(buf)?;
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let buffer_end = self.read_offset + self.read_buffer.len() as u64;
+        if self.offset < self.read_offset || self.offset >= buffer_end {
+            self.write_pending()
+                .and_then(|_| self.fetch_chunk())
+                .map_err(|err| std::io::Error::other(err.to_string()))?;
+        }
 
-        self.bytes_read += nb as u64;
-        self.content = content_slice.to_vec();
+        let start = ((self.offset - self.read_offset) as usize).min(self.read_buffer.len());
+        let nb = (&self.read_buffer[start..]).read(buf)?;
+        self.offset += nb as u64;
         Ok(nb)
     }
 }
-impl<'a> Write for SFTPFile<'a> {
+impl Write for SFTPStreamFile {
     fn write(&mut self, buf: &[u8]) -> Result<usize, std::io::Error> {
-        self.content.write(buf)
+        if self.offset != self.write_offset + self.write_buffer.len() as u64 {
+            self.flush()?;
+        }
+        if self.write_buffer.is_empty() {
+            self.write_offset = self.offset;
+        }
+        self.read_buffer.clear();
+
+        self.write_buffer.extend_from_slice(buf);
+        self.offset += buf.len() as u64;
+        if self.write_buffer.len() >= STREAM_CHUNK_SIZE {
+            self.flush()?;
+        }
+        Ok(buf.len())
     }
     fn flush(&mut self) -> Result<(), std::io::Error> {
-        self.content.flush()
+        self.write_pending()
+            .map_err(|err| std::io::Error::other(err.to_string()))
     }
 }
-impl<'a> Seek for SFTPFile<'a> {
+impl Seek for SFTPStreamFile {
     fn seek(&mut self, pos: SeekFrom) -> Result<u64, std::io::Error> {
-        let err = std::io::Error::new(
-            std::io::ErrorKind::InvalidInput,
-            "Invalid argument: bad cursor value",
-        );
-        match pos {
-            SeekFrom::Current(nb) if self.offset as i64 + nb < self.content.len() as i64 => {
-                let cursor: i64 = self.offset as i64 + nb;
-                if cursor < 0 {
-                    return Err(err);
-                }
-                self.offset = cursor as u64;
-                Ok(cursor as u64)
-            }
-            SeekFrom::End(nb) if nb >= 0 => {
-                self.offset = (self.content.len() as u64) + nb as u64;
-                Ok(self.offset)
-            }
-            SeekFrom::End(nb) if (self.content.len() as i64) + nb >= 0 => {
-                let cursor: i64 = (self.content.len() as i64) + nb;
-                self.offset = cursor as u64;
-                Ok(cursor as u64)
-            }
-            SeekFrom::Start(nb) if nb < self.content.len() as u64 => {
-                self.offset = nb;
-                Ok(nb)
+        let cursor = match pos {
+            SeekFrom::Start(nb) => Some(nb),
+            SeekFrom::End(nb) => self
+                .len()
+                .map_err(|err| std::io::Error::other(err.to_string()))?
+                .checked_add_signed(nb),
+            SeekFrom::Current(nb) => self.offset.checked_add_signed(nb),
+        };
+
+        match cursor {
+            Some(cursor) => {
+                self.offset = cursor;
+                Ok(cursor)
             }
-            _ => Err(err),
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Invalid argument: bad cursor value",
+            )),
         }
     }
 }
+impl Drop for SFTPStreamFile {
+    fn drop(&mut self) {
+        let _ = self.write_pending();
+    }
+}
 
 /// Structure implementing `DirEntry` trait to represent an entry in a directory on a SFTP server
 pub struct SFTPDirEntry {
@@ -383,12 +551,34 @@ impl DirEntry for SFTPDirEntry {
     }
 
     fn file_type(&self) -> Result<FileType, Self::FSError> {
-        if self.stat.is_dir() {
+        if self.stat.file_type().is_symlink() {
+            Ok(FileType::Symlink)
+        } else if self.stat.is_dir() {
             Ok(FileType::Directory)
-        } else if self.stat.is_file() {
+        } else {
             Ok(FileType::File)
+        }
+    }
+}
+
+impl From<FileStat> for Metadata {
+    fn from(file_stat: FileStat) -> Self {
+        let file_type = if file_stat.file_type().is_symlink() {
+            FileType::Symlink
+        } else if file_stat.is_dir() {
+            FileType::Directory
         } else {
-            Ok(FileType::Symlink)
+            FileType::File
+        };
+
+        Metadata {
+            file_type,
+            len: file_stat.size.unwrap_or(0),
+            modified: file_stat
+                .mtime
+                .map(|mtime| UNIX_EPOCH + Duration::from_secs(mtime)),
+            permissions: file_stat.perm.map(Permissions::from_mode),
+            etag: None,
         }
     }
 }
@@ -407,6 +597,13 @@ mod tests {
     use super::*;
     use std::env;
 
+    #[test]
+    fn test_send() {
+        fn assert_send<T: Send>() {}
+        assert_send::<SFTPFileSystem<'static>>();
+        assert_send::<SFTPStreamFile>();
+    }
+
     #[test]
     fn test_create_dir() {
         let sftp_fs = SFTPFileSystem::new(
@@ -421,6 +618,32 @@ mod tests {
         sftp_fs.remove_dir("share/testcreatetest").unwrap();
     }
 
+    #[test]
+    fn test_create_dir_all() {
+        let sftp_fs = SFTPFileSystem::new(
+            String::from("127.0.0.1:2222"),
+            env::var("SSH_USER").expect("SSH_USER environment variable must be set"),
+            None,
+            env::var("SSH_PRIVATE_KEY").expect("SSH_PRIVATE_KEY environment variable must be set"),
+            env::var("SSH_PUBLIC_KEY").expect("SSH_PRIVATE_KEY environment variable must be set"),
+        )
+        .dir_mode(0o700);
+
+        sftp_fs
+            .create_dir_all("share/testcreatedirall/sub/subsub")
+            .unwrap();
+        sftp_fs
+            .create_dir_all("share/testcreatedirall/sub/subsub")
+            .unwrap();
+        sftp_fs
+            .create_file("share/testcreatedirall/sub/myfile")
+            .unwrap();
+        assert!(sftp_fs
+            .create_dir_all("share/testcreatedirall/sub/myfile/other")
+            .is_err());
+        sftp_fs.remove_dir_all("share/testcreatedirall").unwrap();
+    }
+
     #[test]
     fn test_read_dir() {
         let sftp_fs = SFTPFileSystem::new(
@@ -469,6 +692,79 @@ mod tests {
         sftp_fs.remove_file("share/testfull.test").unwrap();
     }
 
+    #[test]
+    fn test_metadata() {
+        let sftp_fs = SFTPFileSystem::new(
+            String::from("127.0.0.1:2222"),
+            env::var("SSH_USER").expect("SSH_USER environment variable must be set"),
+            None,
+            env::var("SSH_PRIVATE_KEY").expect("SSH_PRIVATE_KEY environment variable must be set"),
+            env::var("SSH_PUBLIC_KEY").expect("SSH_PUBLIC_KEY environment variable must be set"),
+        );
+        {
+            let mut file = sftp_fs.create_file("share/testmetadata.test").unwrap();
+            file.write_all(b"coucoutoi").unwrap();
+            file.sync_all().unwrap();
+        }
+
+        assert!(sftp_fs.metadata("share").unwrap().is_dir());
+        let metadata = sftp_fs.metadata("share/testmetadata.test").unwrap();
+        assert!(metadata.is_file());
+        assert_eq!(metadata.len, 9);
+
+        sftp_fs.remove_file("share/testmetadata.test").unwrap();
+    }
+
+    #[test]
+    fn test_symlink() {
+        let sftp_fs = SFTPFileSystem::new(
+            String::from("127.0.0.1:2222"),
+            env::var("SSH_USER").expect("SSH_USER environment variable must be set"),
+            None,
+            env::var("SSH_PRIVATE_KEY").expect("SSH_PRIVATE_KEY environment variable must be set"),
+            env::var("SSH_PUBLIC_KEY").expect("SSH_PUBLIC_KEY environment variable must be set"),
+        );
+        sftp_fs.create_file("share/testsymlink.test").unwrap();
+
+        sftp_fs
+            .symlink("testsymlink.test", "share/testsymlinkbis.test")
+            .unwrap();
+
+        assert_eq!(
+            sftp_fs.read_link("share/testsymlinkbis.test").unwrap(),
+            PathBuf::from("testsymlink.test")
+        );
+        assert!(sftp_fs
+            .metadata("share/testsymlinkbis.test")
+            .unwrap()
+            .is_file());
+        assert_eq!(
+            sftp_fs
+                .symlink_metadata("share/testsymlinkbis.test")
+                .unwrap()
+                .file_type,
+            FileType::Symlink
+        );
+
+        sftp_fs.remove_file("share/testsymlinkbis.test").unwrap();
+        sftp_fs.remove_file("share/testsymlink.test").unwrap();
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        let sftp_fs = SFTPFileSystem::new(
+            String::from("127.0.0.1:2222"),
+            env::var("SSH_USER").expect("SSH_USER environment variable must be set"),
+            None,
+            env::var("SSH_PRIVATE_KEY").expect("SSH_PRIVATE_KEY environment variable must be set"),
+            env::var("SSH_PUBLIC_KEY").expect("SSH_PUBLIC_KEY environment variable must be set"),
+        );
+
+        let share = sftp_fs.canonicalize("share").unwrap();
+        assert!(share.is_absolute());
+        assert_eq!(sftp_fs.canonicalize("share/../share/.").unwrap(), share);
+    }
+
     #[test]
     fn test_remove_dir_all() {
         let sftp_fs = SFTPFileSystem::new(
@@ -487,6 +783,48 @@ mod tests {
         sftp_fs.remove_dir_all("share/testremovedirtest").unwrap();
     }
 
+    #[test]
+    fn test_rename() {
+        let sftp_fs = SFTPFileSystem::new(
+            String::from("127.0.0.1:2222"),
+            env::var("SSH_USER").expect("SSH_USER environment variable must be set"),
+            None,
+            env::var("SSH_PRIVATE_KEY").expect("SSH_PRIVATE_KEY environment variable must be set"),
+            env::var("SSH_PUBLIC_KEY").expect("SSH_PRIVATE_KEY environment variable must be set"),
+        );
+
+        sftp_fs.create_dir("share/testrename").unwrap();
+        let mut file = sftp_fs.create_file("share/testrename/dest").unwrap();
+        file.write_all(b"old").unwrap();
+        file.sync_all().unwrap();
+        assert!(sftp_fs
+            .rename("share/testrename/missing", "share/testrename/dest")
+            .is_err());
+        let mut content = String::new();
+        sftp_fs
+            .open_file("share/testrename/dest")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "old");
+
+        let mut file = sftp_fs.create_file("share/testrename/src").unwrap();
+        file.write_all(b"new").unwrap();
+        file.sync_all().unwrap();
+        sftp_fs
+            .rename("share/testrename/src", "share/testrename/dest")
+            .unwrap();
+        let mut content = String::new();
+        sftp_fs
+            .open_file("share/testrename/dest")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "new");
+        assert_eq!(sftp_fs.read_dir("share/testrename").unwrap().len(), 1);
+        sftp_fs.remove_dir_all("share/testrename").unwrap();
+    }
+
     #[test]
     fn test_seek_file() {
         let sftp_fs = SFTPFileSystem::new(
@@ -514,6 +852,38 @@ mod tests {
         sftp_fs.remove_file("share/testseek.test").unwrap();
     }
 
+    #[test]
+    fn test_stream_file() {
+        let sftp_fs = SFTPFileSystem::new(
+            String::from("127.0.0.1:2222"),
+            env::var("SSH_USER").expect("SSH_USER environment variable must be set"),
+            None,
+            env::var("SSH_PRIVATE_KEY").expect("SSH_PRIVATE_KEY environment variable must be set"),
+            env::var("SSH_PUBLIC_KEY").expect("SSH_PUBLIC_KEY environment variable must be set"),
+        )
+        .file_mode(0o600);
+        {
+            let mut file = sftp_fs.create_file("share/teststream.test").unwrap();
+            file.write_all(b"coucoutoi").unwrap();
+            file.seek(SeekFrom::Start(0)).unwrap();
+            file.write_all(b"C").unwrap();
+            file.sync_all().unwrap();
+        }
+
+        let mut file = sftp_fs.open_file("share/teststream.test").unwrap();
+        assert_eq!(file.seek(SeekFrom::End(-3)).unwrap(), 6);
+        let mut content = String::new();
+        file.read_to_string(&mut content).unwrap();
+        assert_eq!(String::from("toi"), content);
+
+        file.seek(SeekFrom::Start(0)).unwrap();
+        content.clear();
+        file.read_to_string(&mut content).unwrap();
+        assert_eq!(String::from("Coucoutoi"), content);
+
+        sftp_fs.remove_file("share/teststream.test").unwrap();
+    }
+
     #[test]
     fn test_seek_end_file() {
         let sftp_fs = SFTPFileSystem::new(
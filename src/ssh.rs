@@ -1,54 +1,30 @@
 use std::convert::TryInto;
 use std::fs::Permissions;
-use std::io::{Read, Seek, SeekFrom, Write};
-use std::net::TcpStream;
+use std::io::{Read, Seek, SeekFrom, Take, Write};
+use std::os::unix::ffi::OsStrExt;
 use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use ssh2::Session;
+use ssh2::{Channel, ErrorCode, Session};
 
+use crate::checksum::hash_reader;
 use crate::error::ChiconError;
-use crate::{DirEntry, File, FileSystem, FileType};
-
-struct SSHSession {
-    // Only useful to not drop connection
-    _tcp_stream: TcpStream,
-    session: Session,
-}
-impl SSHSession {
-    fn new<P: AsRef<Path>>(
-        addr: String,
-        username: &str,
-        passphrase: Option<&str>,
-        private_key: P,
-        public_key: P,
-    ) -> Result<Self, ChiconError> {
-        let private_key = private_key.as_ref();
-        let public_key = public_key.as_ref();
-        let tcp_stream = TcpStream::connect(addr)?;
-        let mut session = Session::new().ok_or(ChiconError::SFTPError)?;
-
-        session.handshake(&tcp_stream)?;
-        session.userauth_pubkey_file(username, Some(public_key), private_key, passphrase)?;
-
-        Ok(SSHSession {
-            _tcp_stream: tcp_stream,
-            session,
-        })
-    }
-
-    fn session(&self) -> &Session {
-        &self.session
-    }
-}
+use crate::session::{
+    would_block, HostKeyCheck, JumpHost, SSHAuth, SSHConnection, LIBSSH2_ERROR_EAGAIN,
+};
+use crate::{ChecksumAlgorithm, DirEntry, File, FileSystem, FileType, Metadata};
 
 /// Structure implementing `FileSystem` trait to store on a SSH server (via scp)
+///
+/// A single SSH session is opened on first use and shared with the opened files,
+/// it is kept alive and reopened if the connection breaks. The server needs a POSIX shell with
+/// GNU coreutils or BusyBox, `stat -c` and `readlink -e` aren't available on BSD.
 pub struct SSHFileSystem<'a> {
-    username: String,
-    addr: String,
-    passphrase: Option<&'a str>,
-    private_key: PathBuf,
-    public_key: PathBuf,
+    connection: Arc<SSHConnection<'a>>,
+    command_timeout: Option<Duration>,
 }
 impl<'a> SSHFileSystem<'a> {
     pub fn new<P: AsRef<Path>>(
@@ -58,18 +34,152 @@ impl<'a> SSHFileSystem<'a> {
         private_key: P,
         public_key: P,
     ) -> Self {
-        let private_key = private_key.as_ref();
-        let public_key = public_key.as_ref();
+        Self::with_auth(
+            addr,
+            username,
+            SSHAuth::public_key_file(private_key, public_key, passphrase),
+        )
+    }
 
+    /// Create a filesystem authenticating with any of the `SSHAuth` methods
+    pub fn with_auth(addr: String, username: String, auth: SSHAuth<'a>) -> Self {
         SSHFileSystem {
-            username,
-            passphrase,
-            private_key: PathBuf::from(private_key),
-            public_key: PathBuf::from(public_key),
-            addr,
+            connection: Arc::new(SSHConnection::new(addr, username, auth)),
+            command_timeout: None,
         }
     }
+
+    /// Verify the server host key before authenticating, nothing is verified by default
+    pub fn host_key_check(self, host_key_check: HostKeyCheck) -> Self {
+        self.connection.set_host_key_check(host_key_check);
+        self
+    }
+
+    /// Abort the commands run with `exec` if they last longer than `timeout`, they aren't limited by default
+    pub fn command_timeout(mut self, timeout: Duration) -> Self {
+        self.command_timeout = Some(timeout);
+        self
+    }
+
+    /// Reach the server through `jump_host`, successive jump hosts are gone through in order
+    pub fn jump_host(self, jump_host: JumpHost<'a>) -> Self {
+        self.connection.add_jump_host(jump_host);
+        self
+    }
+
+    /// Connect through the stdin/stdout of `proxy_command` run by `sh`, like `ProxyCommand` of OpenSSH
+    ///
+    /// `%h` and `%p` are replaced by the host and port of the first jump host, or of the server.
+    pub fn proxy_command(self, proxy_command: String) -> Self {
+        self.connection.set_proxy_command(proxy_command);
+        self
+    }
+
+    /// Close the SSH session, the next operation opens a new one
+    pub fn disconnect(&self) -> Result<(), ChiconError> {
+        self.connection.disconnect()
+    }
+
+    /// Run `command` through the shell of the server, writing `stdin` to its standard input,
+    /// and return its whole output with its exit status
+    ///
+    /// The command isn't replayed if the connection breaks while it runs.
+    pub fn exec(&self, command: &str, stdin: Option<&[u8]>) -> Result<ExecOutput, ChiconError> {
+        let mut output = ExecOutput::default();
+        output.exit_status =
+            self.exec_streaming(command, stdin, &mut output.stdout, &mut output.stderr)?;
+
+        Ok(output)
+    }
+
+    /// Run `command` like `exec` but write its outputs to `stdout` and `stderr` as they come
+    /// and only return its exit status
+    pub fn exec_streaming<O: Write, E: Write>(
+        &self,
+        command: &str,
+        stdin: Option<&[u8]>,
+        stdout: &mut O,
+        stderr: &mut E,
+    ) -> Result<i32, ChiconError> {
+        let deadline = self.command_timeout.map(|timeout| Instant::now() + timeout);
+        self.connection.with_session_once(|session| {
+            let mut chan = session.channel_session()?;
+            chan.exec(command)?;
+
+            session.set_blocking(false);
+            let res = pump_channel(
+                &mut chan,
+                stdin.unwrap_or_default(),
+                deadline,
+                stdout,
+                stderr,
+            );
+            session.set_blocking(true);
+            if res.is_err() {
+                // The command sees the end of its input and its output closed, the server is
+                // waited for a bounded time so that the channel is freed before the session is reused
+                let timeout = session.timeout();
+                session.set_timeout(CLOSE_TIMEOUT_MS);
+                let _ = chan.send_eof();
+                let _ = chan.close();
+                let _ = chan.wait_close();
+                session.set_timeout(timeout);
+            }
+            res?;
+
+            chan.close()?;
+            chan.wait_close()?;
+            Ok(chan.exit_status()?)
+        })
+    }
+
+    /// Open `path` as a lazy reader, its content is streamed from the server while being read
+    /// so that large files don't have to fit in memory
+    pub fn open_reader<P: AsRef<Path>>(&self, path: P) -> Result<SSHFileReader<'a>, ChiconError> {
+        let path = path.as_ref();
+        let metadata = self.stat(path, true)?;
+
+        Ok(SSHFileReader {
+            path: path.to_path_buf(),
+            metadata,
+            connection: Arc::clone(&self.connection),
+            channel: None,
+            channel_offset: 0,
+            offset: 0,
+        })
+    }
+
+    // Execute `command` on the server and return its output, failing if its exit status isn't 0.
+    // The command is run again if the connection breaks, it must only read from the server.
+    fn run_command(&self, command: &ShellCommand) -> Result<String, ChiconError> {
+        let output = exec_command(&self.connection, command)?;
+        String::from_utf8(output).map_err(|err| ChiconError::SSHExecutionError(err.to_string()))
+    }
+
+    // Like `run_command` but never run again, for commands modifying the server
+    fn run_command_once(&self, command: &ShellCommand) -> Result<(), ChiconError> {
+        exec_command_once(&self.connection, command)?;
+        Ok(())
+    }
+
+    fn stat(&self, path: &Path, follow_links: bool) -> Result<Metadata, ChiconError> {
+        let mut command = ShellCommand::new("stat");
+        if follow_links {
+            command = command.arg("-L");
+        }
+        let output = self.run_command(
+            &command
+                .arg("-c")
+                .arg("%F|%s|%Y|%a")
+                .end_of_options()
+                .path(path),
+        )?;
+
+        parse_stat_output(output.trim_end())
+            .ok_or_else(|| ChiconError::SSHExecutionError(output.clone()))
+    }
 }
+
 impl<'a> FileSystem for SSHFileSystem<'a> {
     type FSError = ChiconError;
     type File = SSHFile<'a>;
@@ -77,210 +187,133 @@ impl<'a> FileSystem for SSHFileSystem<'a> {
 
     fn chmod<P: AsRef<Path>>(&self, path: P, perm: Permissions) -> Result<(), Self::FSError> {
         let path = path.as_ref();
-        let ssh_session = SSHSession::new(
-            self.addr.clone(),
-            &self.username,
-            self.passphrase,
-            self.private_key.as_path(),
-            self.public_key.as_path(),
+        self.run_command_once(
+            &ShellCommand::new("chmod")
+                .arg(format!("{:o}", perm.mode() & 0o7777))
+                .end_of_options()
+                .path(path),
         )?;
-        let session = ssh_session.session();
-
-        let mut chan = session.channel_session()?;
-        chan.exec(
-            format!(
-                "chmod {} {}",
-                perm.mode(),
-                path.to_str().ok_or(ChiconError::BadPath)?
-            )
-            .as_str(),
-        )?;
-        let mut output = String::new();
-        chan.read_to_string(&mut output)?;
-        chan.wait_eof()?;
-        chan.close()?;
-        chan.wait_close()?;
-
-        if chan.exit_status()? != 0 {
-            return Err(ChiconError::SSHExecutionError(output));
-        }
         Ok(())
     }
 
     fn create_file<P: AsRef<Path>>(&self, path: P) -> Result<Self::File, Self::FSError> {
         let path = path.as_ref();
-        let ssh_session = SSHSession::new(
-            self.addr.clone(),
-            &self.username,
-            self.passphrase,
-            self.private_key.as_path(),
-            self.public_key.as_path(),
-        )?;
-        let session = ssh_session.session();
-
-        let mut my_chan = session.channel_session()?;
-        my_chan.exec(format!("touch {}", path.to_str().ok_or(ChiconError::BadPath)?).as_str())?;
-        let mut output = String::new();
-        my_chan.read_to_string(&mut output)?;
-        my_chan.wait_eof()?;
-        my_chan.close()?;
-        my_chan.wait_close()?;
-
-        if my_chan.exit_status()? != 0 {
-            return Err(ChiconError::SSHExecutionError(output));
-        }
+        self.run_command_once(&ShellCommand::new("touch").end_of_options().path(path))?;
 
         Ok(SSHFile::new(
             PathBuf::from(path),
             Vec::<u8>::new(),
-            self.addr.clone(),
-            self.username.clone(),
-            self.passphrase,
-            &self.private_key,
-            &self.public_key,
+            Arc::clone(&self.connection),
         ))
     }
 
     fn create_dir<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
         let path = path.as_ref();
-        let ssh_session = SSHSession::new(
-            self.addr.clone(),
-            &self.username,
-            self.passphrase,
-            self.private_key.as_path(),
-            self.public_key.as_path(),
-        )?;
-        let session = ssh_session.session();
-
-        let mut my_chan = session.channel_session()?;
-        my_chan.exec(format!("mkdir {}", path.to_str().ok_or(ChiconError::BadPath)?).as_str())?;
-        let mut output = String::new();
-        my_chan.read_to_string(&mut output)?;
-        my_chan.wait_eof()?;
-        my_chan.close()?;
-        my_chan.wait_close()?;
-
-        if my_chan.exit_status()? != 0 {
-            return Err(ChiconError::SSHExecutionError(output));
-        }
+        self.run_command_once(&ShellCommand::new("mkdir").end_of_options().path(path))?;
 
         Ok(())
     }
 
     fn create_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
         let path = path.as_ref();
-        let ssh_session = SSHSession::new(
-            self.addr.clone(),
-            &self.username,
-            self.passphrase,
-            self.private_key.as_path(),
-            self.public_key.as_path(),
+        self.run_command_once(
+            &ShellCommand::new("mkdir")
+                .arg("-p")
+                .end_of_options()
+                .path(path),
         )?;
-        let session = ssh_session.session();
-        let mut my_chan = session.channel_session()?;
-
-        my_chan
-            .exec(format!("mkdir -p {}", path.to_str().ok_or(ChiconError::BadPath)?).as_str())?;
-        let mut output = String::new();
-        my_chan.read_to_string(&mut output)?;
-        my_chan.wait_eof()?;
-        my_chan.close()?;
-        my_chan.wait_close()?;
-
-        if my_chan.exit_status()? != 0 {
-            return Err(ChiconError::SSHExecutionError(output));
-        }
 
         Ok(())
     }
 
     fn open_file<P: AsRef<Path>>(&self, path: P) -> Result<Self::File, Self::FSError> {
         let path = path.as_ref();
-        let ssh_session = SSHSession::new(
-            self.addr.clone(),
-            &self.username,
-            self.passphrase,
-            self.private_key.as_path(),
-            self.public_key.as_path(),
-        )?;
-        let session = ssh_session.session();
-        let mut my_chan = session.channel_session()?;
-
-        my_chan.exec(format!("cat {}", path.to_str().ok_or(ChiconError::BadPath)?).as_str())?;
-        let mut output = String::new();
-        my_chan.read_to_string(&mut output)?;
-        my_chan.wait_eof()?;
-        my_chan.close()?;
-        my_chan.wait_close()?;
-
-        if my_chan.exit_status()? != 0 {
-            return Err(ChiconError::SSHExecutionError(output));
-        }
+        let (channel, stat) = self.connection.with_session(|session| {
+            let (chan, stat) = session.scp_recv(path)?;
+            Ok((chan.take(stat.size()), stat))
+        })?;
 
-        Ok(SSHFile::new(
+        // The content is only downloaded while being read, or all at once before being modified
+        let mut file = SSHFile::new(
             PathBuf::from(path),
-            output.into_bytes(),
-            self.addr.clone(),
-            self.username.clone(),
-            self.passphrase,
-            &self.private_key,
-            &self.public_key,
-        ))
+            Vec::new(),
+            Arc::clone(&self.connection),
+        );
+        file.size = stat.size();
+        file.mode = stat.mode() & 0o7777;
+        file.reader = Some(SSHFileReader {
+            path: path.to_path_buf(),
+            metadata: Metadata {
+                file_type: FileType::File,
+                len: stat.size(),
+                modified: None,
+                permissions: Some(Permissions::from_mode(file.mode as u32)),
+                etag: None,
+            },
+            connection: Arc::clone(&self.connection),
+            channel: Some(channel),
+            channel_offset: 0,
+            offset: 0,
+        });
+        Ok(file)
     }
 
     fn read_dir<P: AsRef<Path>>(&self, path: P) -> Result<Vec<Self::DirEntry>, Self::FSError> {
         let path = path.as_ref();
-        let ssh_session = SSHSession::new(
-            self.addr.clone(),
-            &self.username,
-            self.passphrase,
-            self.private_key.as_path(),
-            self.public_key.as_path(),
-        )?;
-        let session = ssh_session.session();
-        let mut my_chan = session.channel_session()?;
-
-        my_chan.exec(format!("ls -Ap {}", path.to_str().ok_or(ChiconError::BadPath)?).as_str())?;
-        let mut output = String::new();
-        my_chan.read_to_string(&mut output)?;
-        my_chan.wait_eof()?;
-        my_chan.close()?;
-        my_chan.wait_close()?;
-
-        if my_chan.exit_status()? != 0 {
-            return Err(ChiconError::SSHExecutionError(output));
+        // find has no `--`, a leading `./` keeps the path from being taken as an option
+        let dir = if path.as_os_str().as_bytes().first() == Some(&b'-') {
+            Path::new(".").join(path)
+        } else {
+            path.to_path_buf()
+        };
+        let listing = self.run_command(
+            &ShellCommand::new("find")
+                .path(&dir)
+                .arg("-mindepth")
+                .arg("1")
+                .arg("-maxdepth")
+                .arg("1")
+                .arg("-printf")
+                .arg(FIND_FORMAT),
+        );
+        if let Ok(output) = listing {
+            return parse_find_output(path, &output)
+                .ok_or_else(|| ChiconError::SSHExecutionError(output.clone()));
         }
 
-        let mut entries: Vec<Self::DirEntry> = Vec::new();
-        for entry in output.split_whitespace() {
-            entries.push(SSHDirEntry::new(path, entry))
-        }
+        // `-printf` is a GNU extension, other finds (BusyBox, BSD) run `stat` on each entry instead,
+        // followed by a NUL that `stat -c` can't print itself
+        let output = self.run_command(
+            &ShellCommand::new("find")
+                .path(dir.join("."))
+                .arg("!")
+                .arg("-name")
+                .arg(".")
+                .arg("-prune")
+                .arg("-exec")
+                .arg("sh")
+                .arg("-c")
+                .arg(format!(
+                    "for f; do stat -c '{}' -- \"$f\" || exit 1; printf '\\0'; done",
+                    STAT_LISTING_FORMAT
+                ))
+                .arg("sh")
+                .arg("{}")
+                .arg("+"),
+        )?;
 
-        Ok(entries)
+        parse_stat_listing(path, &output)
+            .ok_or_else(|| ChiconError::SSHExecutionError(output.clone()))
     }
 
     fn remove_file<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
         let path = path.as_ref();
-        let ssh_session = SSHSession::new(
-            self.addr.clone(),
-            &self.username,
-            self.passphrase,
-            self.private_key.as_path(),
-            self.public_key.as_path(),
+        self.run_command_once(
+            &ShellCommand::new("rm")
+                .arg("-f")
+                .end_of_options()
+                .path(path),
         )?;
-        let session = ssh_session.session();
-        let mut my_chan = session.channel_session()?;
-
-        my_chan.exec(format!("rm -f {}", path.to_str().ok_or(ChiconError::BadPath)?).as_str())?;
-        let mut output = String::new();
-        my_chan.read_to_string(&mut output)?;
-        my_chan.wait_eof()?;
-        my_chan.close()?;
-        my_chan.wait_close()?;
-
-        if my_chan.exit_status()? != 0 {
-            return Err(ChiconError::SSHExecutionError(output));
-        }
 
         Ok(())
     }
@@ -291,26 +324,12 @@ impl<'a> FileSystem for SSHFileSystem<'a> {
 
     fn remove_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
         let path = path.as_ref();
-        let ssh_session = SSHSession::new(
-            self.addr.clone(),
-            &self.username,
-            self.passphrase,
-            self.private_key.as_path(),
-            self.public_key.as_path(),
+        self.run_command_once(
+            &ShellCommand::new("rm")
+                .arg("-rf")
+                .end_of_options()
+                .path(path),
         )?;
-        let session = ssh_session.session();
-        let mut my_chan = session.channel_session()?;
-
-        my_chan.exec(format!("rm -rf {}", path.to_str().ok_or(ChiconError::BadPath)?).as_str())?;
-        let mut output = String::new();
-        my_chan.read_to_string(&mut output)?;
-        my_chan.wait_eof()?;
-        my_chan.close()?;
-        my_chan.wait_close()?;
-
-        if my_chan.exit_status()? != 0 {
-            return Err(ChiconError::SSHExecutionError(output));
-        }
 
         Ok(())
     }
@@ -318,110 +337,520 @@ impl<'a> FileSystem for SSHFileSystem<'a> {
     fn rename<P: AsRef<Path>>(&self, from: P, to: P) -> Result<(), Self::FSError> {
         let from = from.as_ref();
         let to = to.as_ref();
-        let ssh_session = SSHSession::new(
-            self.addr.clone(),
-            &self.username,
-            self.passphrase,
-            self.private_key.as_path(),
-            self.public_key.as_path(),
+        self.run_command_once(
+            &ShellCommand::new("mv")
+                .arg("-f")
+                .end_of_options()
+                .path(from)
+                .path(to),
         )?;
-        let session = ssh_session.session();
-        let mut my_chan = session.channel_session()?;
 
-        my_chan.exec(
-            format!(
-                "mv -f {} {}",
-                from.to_str().ok_or(ChiconError::BadPath)?,
-                to.to_str().ok_or(ChiconError::BadPath)?
-            )
-            .as_str(),
+        Ok(())
+    }
+
+    fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata, Self::FSError> {
+        self.stat(path.as_ref(), true)
+    }
+
+    /// Set with `touch -d @SECONDS`, to the second
+    fn set_modified<P: AsRef<Path>>(
+        &self,
+        path: P,
+        modified: SystemTime,
+    ) -> Result<(), Self::FSError> {
+        let secs = modified
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+        self.run_command_once(
+            &ShellCommand::new("touch")
+                .arg("-c")
+                .arg("-m")
+                .arg("-d")
+                .arg(format!("@{}", secs))
+                .end_of_options()
+                .path(path.as_ref()),
+        )
+    }
+
+    fn symlink<P: AsRef<Path>>(&self, target: P, link: P) -> Result<(), Self::FSError> {
+        let target = target.as_ref();
+        let link = link.as_ref();
+        self.run_command_once(
+            &ShellCommand::new("ln")
+                .arg("-s")
+                .end_of_options()
+                .path(target)
+                .path(link),
+        )?;
+
+        Ok(())
+    }
+
+    fn read_link<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Self::FSError> {
+        let path = path.as_ref();
+        let output =
+            self.run_command(&ShellCommand::new("readlink").end_of_options().path(path))?;
+
+        Ok(PathBuf::from(output.trim_end_matches('\n')))
+    }
+
+    fn symlink_metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata, Self::FSError> {
+        self.stat(path.as_ref(), false)
+    }
+
+    fn canonicalize<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Self::FSError> {
+        let path = path.as_ref();
+        let output = self.run_command(
+            // `-e` fails like `std::fs::canonicalize` when a component doesn't exist
+            &ShellCommand::new("readlink")
+                .arg("-e")
+                .end_of_options()
+                .path(path),
+        )?;
+
+        Ok(PathBuf::from(output.trim_end_matches('\n')))
+    }
+
+    fn checksum<P: AsRef<Path>>(
+        &self,
+        path: P,
+        algorithm: ChecksumAlgorithm,
+    ) -> Result<String, Self::FSError> {
+        let path = path.as_ref();
+        let command = match algorithm {
+            ChecksumAlgorithm::Md5 => "md5sum",
+            ChecksumAlgorithm::Sha256 => "sha256sum",
+            // No standard command computes crc32c remotely
+            ChecksumAlgorithm::Crc32c => {
+                let mut file = self.open_file(path)?;
+                return hash_reader(&mut file, algorithm).map_err(ChiconError::from);
+            }
+        };
+        let output = self.run_command(&ShellCommand::new(command).end_of_options().path(path))?;
+
+        // The hash is prefixed by a backslash when the file name contains a backslash or a newline
+        output
+            .split_whitespace()
+            .next()
+            .map(|hash| String::from(hash.trim_start_matches('\\')))
+            .ok_or_else(|| ChiconError::SSHExecutionError(output.clone()))
+    }
+
+    fn copy<P: AsRef<Path>>(&self, from: P, to: P) -> Result<(), Self::FSError> {
+        let from = from.as_ref();
+        let to = to.as_ref();
+        self.run_command_once(
+            &ShellCommand::new("cp")
+                .arg("-a")
+                .end_of_options()
+                .path(from)
+                .path(to),
         )?;
-        let mut output = String::new();
-        my_chan.read_to_string(&mut output)?;
-        my_chan.wait_eof()?;
-        my_chan.close()?;
-        my_chan.wait_close()?;
-
-        if my_chan.exit_status()? != 0 {
-            return Err(ChiconError::SSHExecutionError(output));
-        }
 
         Ok(())
     }
+
+    fn copy_dir_all<P: AsRef<Path>>(&self, from: P, to: P) -> Result<(), Self::FSError> {
+        let from = from.as_ref();
+        let to = to.as_ref();
+
+        // Copy the content of `from` and not `from` itself when `to` already exists
+        let mkdir = ShellCommand::new("mkdir")
+            .arg("-p")
+            .end_of_options()
+            .path(to);
+        let cp = ShellCommand::new("cp")
+            .arg("-a")
+            .end_of_options()
+            .path(from.join("."))
+            .path(to);
+        self.run_command_once(&mkdir.and(cp))?;
+
+        Ok(())
+    }
+}
+
+// Execute `command` on the server and return its output, failing if its exit status isn't 0.
+// The command is run again if the connection breaks, it must only read from the server.
+fn exec_command(
+    connection: &SSHConnection,
+    command: &ShellCommand,
+) -> Result<Vec<u8>, ChiconError> {
+    connection.with_session(|session| exec_in_session(session, command))
+}
+
+// Like `exec_command` but never run again, for commands modifying the server
+fn exec_command_once(
+    connection: &SSHConnection,
+    command: &ShellCommand,
+) -> Result<Vec<u8>, ChiconError> {
+    connection.with_session_once(|session| exec_in_session(session, command))
+}
+
+fn exec_in_session(session: &Session, command: &ShellCommand) -> Result<Vec<u8>, ChiconError> {
+    let mut my_chan = session.channel_session()?;
+
+    my_chan.exec(command.as_str())?;
+    let mut output = Vec::new();
+    my_chan.read_to_end(&mut output)?;
+    my_chan.wait_eof()?;
+    my_chan.close()?;
+    my_chan.wait_close()?;
+
+    if my_chan.exit_status()? != 0 {
+        return Err(ChiconError::SSHExecutionError(
+            String::from_utf8_lossy(&output).into_owned(),
+        ));
+    }
+
+    Ok(output)
+}
+
+/// Output of a command run with `SSHFileSystem::exec`
+#[derive(Clone, Debug, Default)]
+pub struct ExecOutput {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    pub exit_status: i32,
+}
+impl ExecOutput {
+    /// Fail with `SSHExecutionError` holding stderr if the exit status isn't 0
+    pub fn check(self) -> Result<Self, ChiconError> {
+        if self.exit_status != 0 {
+            return Err(ChiconError::SSHExecutionError(
+                String::from_utf8_lossy(&self.stderr).into_owned(),
+            ));
+        }
+
+        Ok(self)
+    }
+}
+
+// Milliseconds given to the server to close the channel of a command which failed or timed out
+const CLOSE_TIMEOUT_MS: u32 = 5000;
+
+// Feed `stdin` to a non blocking channel and copy its outputs until it reaches EOF,
+// failing with `SSHExecutionError` once `deadline` is passed
+fn pump_channel<O: Write, E: Write>(
+    chan: &mut Channel,
+    stdin: &[u8],
+    deadline: Option<Instant>,
+    stdout: &mut O,
+    stderr: &mut E,
+) -> Result<(), ChiconError> {
+    let mut buffer = [0; 32 * 1024];
+    let mut written = 0;
+    let mut eof_sent = false;
+
+    loop {
+        // Checked on every turn, a command flooding its output would otherwise never time out
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return Err(ChiconError::SSHExecutionError(String::from(
+                "command timed out",
+            )));
+        }
+
+        let mut progress = false;
+        if written < stdin.len() {
+            match chan.write(&stdin[written..]) {
+                Ok(nb) => {
+                    written += nb;
+                    progress = true;
+                }
+                Err(ref err) if would_block(err) => {}
+                Err(err) => return Err(err.into()),
+            }
+        } else if !eof_sent {
+            match chan.send_eof() {
+                Ok(()) => {
+                    eof_sent = true;
+                    progress = true;
+                }
+                Err(ref err) if err.code() == ErrorCode::Session(LIBSSH2_ERROR_EAGAIN) => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+        progress |= copy_available(&mut chan.stream(0), stdout, &mut buffer)?;
+        progress |= copy_available(&mut chan.stderr(), stderr, &mut buffer)?;
+
+        if !progress {
+            if chan.eof() {
+                return Ok(());
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+// Copy what can be read from `stream` without blocking, returning whether anything was read
+fn copy_available<R: Read, W: Write>(
+    stream: &mut R,
+    output: &mut W,
+    buffer: &mut [u8],
+) -> Result<bool, ChiconError> {
+    match stream.read(buffer) {
+        Ok(0) => Ok(false),
+        Ok(nb) => {
+            output.write_all(&buffer[..nb])?;
+            Ok(true)
+        }
+        Err(ref err) if would_block(err) => Ok(false),
+        Err(err) => Err(err.into()),
+    }
+}
+
+// Type, size, mtime, mode, owner, group and name of each entry, NUL-terminated so that any name can be parsed
+const FIND_FORMAT: &str = "%y|%s|%T@|%m|%u|%g|%f\\0";
+
+// Type, size, mtime, mode, owner, group and path of an entry, `stat` ends it with a newline
+const STAT_LISTING_FORMAT: &str = "%F|%s|%Y|%a|%U|%G|%n";
+
+// Parse the output of `stat -c STAT_LISTING_FORMAT` run on each entry, each one followed by a NUL
+// so that names containing a newline can be parsed
+fn parse_stat_listing(root_path: &Path, output: &str) -> Option<Vec<SSHDirEntry>> {
+    let mut entries = Vec::new();
+    for record in output.split('\0').filter(|record| !record.is_empty()) {
+        // The path comes last as it may contain the separator
+        let mut fields = record.strip_suffix('\n')?.splitn(7, '|');
+        let metadata = parse_stat_fields(&mut fields)?;
+        let owner = String::from(fields.next()?);
+        let group = String::from(fields.next()?);
+        let name = fields.next()?.rsplit('/').next()?;
+
+        entries.push(SSHDirEntry {
+            path: root_path.join(name),
+            metadata,
+            owner: Some(owner),
+            group: Some(group),
+        });
+    }
+
+    Some(entries)
+}
+
+// Parse the output of `find -printf FIND_FORMAT`
+fn parse_find_output(root_path: &Path, output: &str) -> Option<Vec<SSHDirEntry>> {
+    let mut entries = Vec::new();
+    for record in output.split('\0').filter(|record| !record.is_empty()) {
+        // The name comes last as it may contain the separator
+        let mut fields = record.splitn(7, '|');
+        let file_type = match fields.next()? {
+            "d" => FileType::Directory,
+            "l" => FileType::Symlink,
+            _ => FileType::File,
+        };
+        let len = fields.next()?.parse::<u64>().ok()?;
+        let modified = parse_timestamp(fields.next()?)?;
+        let mode = u32::from_str_radix(fields.next()?, 8).ok()?;
+        let owner = String::from(fields.next()?);
+        let group = String::from(fields.next()?);
+        let name = fields.next()?;
+
+        entries.push(SSHDirEntry {
+            path: root_path.join(name),
+            metadata: Metadata {
+                file_type,
+                len,
+                modified: Some(modified),
+                permissions: Some(Permissions::from_mode(mode)),
+                etag: None,
+            },
+            owner: Some(owner),
+            group: Some(group),
+        });
+    }
+
+    Some(entries)
+}
+
+// Parse a `seconds.fraction` timestamp as printed by `%T@`
+fn parse_timestamp(timestamp: &str) -> Option<SystemTime> {
+    let (secs, fraction) = match timestamp.find('.') {
+        Some(idx) => (&timestamp[..idx], &timestamp[idx + 1..]),
+        None => (timestamp, ""),
+    };
+    let mut nanos = fraction.chars().take(9).collect::<String>();
+    while nanos.len() < 9 {
+        nanos.push('0');
+    }
+
+    Some(UNIX_EPOCH + Duration::new(secs.parse::<u64>().ok()?, nanos.parse::<u32>().ok()?))
+}
+
+/// Command line run on a SSH server, every argument is quoted for a POSIX shell
+struct ShellCommand {
+    line: String,
+}
+impl ShellCommand {
+    fn new(program: &str) -> Self {
+        ShellCommand {
+            line: shell_quote(program),
+        }
+    }
+
+    fn arg<S: AsRef<str>>(mut self, arg: S) -> Self {
+        self.line.push(' ');
+        self.line.push_str(&shell_quote(arg.as_ref()));
+        self
+    }
+
+    fn path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.line.push(' ');
+        self.line.push_str(&shell_quote_path(path.as_ref()));
+        self
+    }
+
+    // Following arguments aren't taken as options even if they start with `-`
+    fn end_of_options(self) -> Self {
+        self.arg("--")
+    }
+
+    // Run `next` only if this command succeeds
+    fn and(mut self, next: ShellCommand) -> Self {
+        self.line.push_str(" && ");
+        self.line.push_str(&next.line);
+        self
+    }
+
+    fn as_str(&self) -> &str {
+        &self.line
+    }
+}
+
+// Quote `arg` for a POSIX shell, single quotes protect everything but themselves
+fn shell_quote(arg: &str) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "%+,-./:=@_".contains(c);
+    if !arg.is_empty() && arg.chars().all(is_safe) {
+        return String::from(arg);
+    }
+
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+// Quote `path` for a POSIX shell, a name which isn't valid UTF-8 is rebuilt by `printf` from octal escapes,
+// `-` included so that the format is never taken as an option
+fn shell_quote_path(path: &Path) -> String {
+    if let Some(path) = path.to_str() {
+        return shell_quote(path);
+    }
+
+    let mut escaped = String::new();
+    for byte in path.as_os_str().as_bytes() {
+        if byte.is_ascii_alphanumeric() || b"+,./:=@_".contains(byte) {
+            escaped.push(*byte as char);
+        } else {
+            escaped.push_str(&format!("\\{:03o}", byte));
+        }
+    }
+
+    format!("\"$(printf '{}')\"", escaped)
+}
+
+// Parse the output of `stat -c '%F|%s|%Y|%a'`
+fn parse_stat_output(output: &str) -> Option<Metadata> {
+    parse_stat_fields(&mut output.split('|'))
+}
+
+// Parse the type, size, mtime and mode printed by `stat -c "%F|%s|%Y|%a"`
+fn parse_stat_fields<'s, I: Iterator<Item = &'s str>>(fields: &mut I) -> Option<Metadata> {
+    let file_type = match fields.next()? {
+        "directory" => FileType::Directory,
+        "symbolic link" => FileType::Symlink,
+        _ => FileType::File,
+    };
+    let len = fields.next()?.parse::<u64>().ok()?;
+    let modified = fields.next()?.parse::<u64>().ok()?;
+    let mode = u32::from_str_radix(fields.next()?, 8).ok()?;
+
+    Some(Metadata {
+        file_type,
+        len,
+        modified: Some(UNIX_EPOCH + Duration::from_secs(modified)),
+        permissions: Some(Permissions::from_mode(mode)),
+        etag: None,
+    })
 }
 
 /// Structure implementing `File` trait to represent a file on a SSH server (via scp)
 pub struct SSHFile<'a> {
     filename: PathBuf,
     content: Vec<u8>,
-    addr: String,
-    username: String,
-    passphrase: Option<&'a str>,
-    private_key: PathBuf,
-    public_key: PathBuf,
+    size: u64,
+    mode: i32,
+    connection: Arc<SSHConnection<'a>>,
     offset: u64,
     bytes_read: u64,
+    // Remote content not downloaded yet, for a file got from `open_file`
+    reader: Option<SSHFileReader<'a>>,
 }
 impl<'a> SSHFile<'a> {
-    fn new<P>(
-        filename: PathBuf,
-        content: Vec<u8>,
-        addr: String,
-        username: String,
-        passphrase: Option<&'a str>,
-        private_key: P,
-        public_key: P,
-    ) -> Self
-    where
-        P: AsRef<Path>,
-    {
-        let private_key = private_key.as_ref();
-        let public_key = public_key.as_ref();
-
+    fn new(filename: PathBuf, content: Vec<u8>, connection: Arc<SSHConnection<'a>>) -> Self {
         SSHFile {
             filename,
+            size: content.len() as u64,
             content,
-            username,
-            passphrase,
-            private_key: PathBuf::from(private_key),
-            public_key: PathBuf::from(public_key),
-            addr,
+            mode: 0o755,
+            connection,
             offset: 0,
             bytes_read: 0,
+            reader: None,
         }
     }
+
+    // Download the rest of the remote content, before it's modified or seeked into
+    fn load(&mut self) -> Result<(), std::io::Error> {
+        if let Some(mut reader) = self.reader.take() {
+            reader.read_to_end(&mut self.content)?;
+        }
+
+        Ok(())
+    }
+
+    /// Size of the remote file when it was opened
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Mode of the remote file when it was opened, given back to it by `sync_all`
+    pub fn mode(&self) -> i32 {
+        self.mode
+    }
 }
 impl<'a> File for SSHFile<'a> {
     type FSError = ChiconError;
 
     fn sync_all(&mut self) -> Result<(), Self::FSError> {
-        let tcp_stream = TcpStream::connect(self.addr.clone())?;
-        let mut session = Session::new().ok_or(ChiconError::SFTPError)?;
-        session.handshake(&tcp_stream)?;
-        session.userauth_pubkey_file(
-            &self.username,
-            Some(self.public_key.as_path()),
-            self.private_key.as_path(),
-            self.passphrase,
-        )?;
-
-        let mut chan = session.scp_send(
-            self.filename.as_path(),
-            0o755,
-            self.content.len().try_into().unwrap(),
-            None,
-        )?;
-
-        chan.write_all(self.content.as_slice())?;
-        chan.send_eof()?;
-        chan.wait_eof()?;
-        chan.close()?;
-        chan.wait_close().map_err(ChiconError::from)
+        self.load()?;
+        let filename = &self.filename;
+        let content = &self.content;
+        let mode = self.mode;
+
+        self.connection.with_session_once(|session| {
+            let mut chan = session.scp_send(
+                filename.as_path(),
+                mode,
+                content.len().try_into().unwrap(),
+                None,
+            )?;
+
+            chan.write_all(content.as_slice())?;
+            chan.send_eof()?;
+            chan.wait_eof()?;
+            chan.close()?;
+            chan.wait_close().map_err(ChiconError::from)
+        })
     }
 }
 
 impl<'a> Read for SSHFile<'a> {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+        if let Some(reader) = &mut self.reader {
+            let nb = reader.read(buf)?;
+            self.bytes_read += nb as u64;
+            return Ok(nb);
+        }
+
         let mut content_slice = if self.bytes_read == 0 {
             if self.offset >= self.content.len() as u64 {
                 return Ok(0);
@@ -439,6 +868,7 @@ impl<'a> Read for SSHFile<'a> {
 }
 impl<'a> Write for SSHFile<'a> {
     fn write(&mut self, buf: &[u8]) -> Result<usize, std::io::Error> {
+        self.load()?;
         self.content.write(buf)
     }
     fn flush(&mut self) -> Result<(), std::io::Error> {
@@ -447,6 +877,7 @@ impl<'a> Write for SSHFile<'a> {
 }
 impl<'a> Seek for SSHFile<'a> {
     fn seek(&mut self, pos: SeekFrom) -> Result<u64, std::io::Error> {
+        self.load()?;
         let err = std::io::Error::new(
             std::io::ErrorKind::InvalidInput,
             "Invalid argument: bad cursor value",
@@ -478,12 +909,120 @@ impl<'a> Seek for SSHFile<'a> {
     }
 }
 
+/// Lazy reader of a file on a SSH server, got from `SSHFileSystem::open_reader`
+///
+/// The content is streamed through a scp channel of the shared session, opened on the first read.
+/// Seeking forward skips the bytes in between, seeking backward downloads the file again from its start.
+pub struct SSHFileReader<'a> {
+    path: PathBuf,
+    metadata: Metadata,
+    connection: Arc<SSHConnection<'a>>,
+    channel: Option<Take<Channel>>,
+    // Position of the next byte read from `channel`
+    channel_offset: u64,
+    offset: u64,
+}
+impl<'a> SSHFileReader<'a> {
+    /// Metadata of the remote file fetched when opening it, giving its size and mode up front
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    fn open_channel(&mut self) -> Result<(), ChiconError> {
+        let path = self.path.as_path();
+        let channel = self.connection.with_session(|session| {
+            let (chan, stat) = session.scp_recv(path)?;
+            Ok(chan.take(stat.size()))
+        })?;
+        self.channel = Some(channel);
+        self.channel_offset = 0;
+
+        Ok(())
+    }
+
+    // The scp protocol sends a status byte after the content, the channel is closed without reading it
+    fn close_channel(&mut self) -> Result<(), ChiconError> {
+        if let Some(channel) = self.channel.take() {
+            let mut chan = channel.into_inner();
+            chan.send_eof()?;
+            chan.wait_eof()?;
+            chan.close()?;
+            chan.wait_close()?;
+        }
+
+        Ok(())
+    }
+
+    fn truncated(&self) -> std::io::Error {
+        std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            format!(
+                "{} truncated at {} bytes instead of {}",
+                self.path.display(),
+                self.channel_offset,
+                self.metadata.len
+            ),
+        )
+    }
+}
+impl<'a> Read for SSHFileReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+        if self.offset >= self.metadata.len || buf.is_empty() {
+            return Ok(0);
+        }
+        if self.channel.is_none() || self.offset < self.channel_offset {
+            self.open_channel()
+                .map_err(|err| std::io::Error::other(err.to_string()))?;
+        }
+
+        let channel = self.channel.as_mut().unwrap();
+        let skip = self.offset - self.channel_offset;
+        let skipped = std::io::copy(&mut channel.by_ref().take(skip), &mut std::io::sink())?;
+        let max = buf.len().min((self.metadata.len - self.offset) as usize);
+        let nb = channel.read(&mut buf[..max])?;
+        self.channel_offset += skipped + nb as u64;
+        if skipped < skip || nb == 0 {
+            return Err(self.truncated());
+        }
+
+        self.offset += nb as u64;
+        if self.offset == self.metadata.len {
+            self.close_channel()
+                .map_err(|err| std::io::Error::other(err.to_string()))?;
+        }
+        Ok(nb)
+    }
+}
+impl<'a> Seek for SSHFileReader<'a> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, std::io::Error> {
+        let cursor = match pos {
+            SeekFrom::Start(nb) => Some(nb),
+            SeekFrom::End(nb) => self.metadata.len.checked_add_signed(nb),
+            SeekFrom::Current(nb) => self.offset.checked_add_signed(nb),
+        };
+
+        match cursor {
+            Some(cursor) => {
+                self.offset = cursor;
+                Ok(cursor)
+            }
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Invalid argument: bad cursor value",
+            )),
+        }
+    }
+}
+
 /// Structure implementing `DirEntry` trait to represent an entry in a directory on a SSH server
 pub struct SSHDirEntry {
     path: PathBuf,
-    file_type: FileType,
+    metadata: Metadata,
+    owner: Option<String>,
+    group: Option<String>,
 }
 impl SSHDirEntry {
+    /// Entry from a line of `ls -p`, directories ending with `/`, without metadata
     pub fn new(root_path: &Path, raw_path: &str) -> Self {
         let file_type = if raw_path.ends_with('/') {
             FileType::Directory
@@ -492,10 +1031,33 @@ impl SSHDirEntry {
         };
 
         SSHDirEntry {
-            file_type,
             path: root_path.join(raw_path.trim_end_matches('/')),
+            metadata: Metadata {
+                file_type,
+                len: 0,
+                modified: None,
+                permissions: None,
+                etag: None,
+            },
+            owner: None,
+            group: None,
         }
     }
+
+    /// Metadata of the entry itself, symlinks are not followed
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// Name of the user owning the entry
+    pub fn owner(&self) -> Option<&str> {
+        self.owner.as_deref()
+    }
+
+    /// Name of the group owning the entry
+    pub fn group(&self) -> Option<&str> {
+        self.group.as_deref()
+    }
 }
 impl DirEntry for SSHDirEntry {
     type FSError = ssh2::Error;
@@ -505,7 +1067,7 @@ impl DirEntry for SSHDirEntry {
     }
 
     fn file_type(&self) -> Result<FileType, Self::FSError> {
-        Ok(self.file_type.clone())
+        Ok(self.metadata.file_type.clone())
     }
 }
 
@@ -514,6 +1076,148 @@ mod tests {
     use super::*;
     use std::env;
 
+    #[test]
+    fn test_send() {
+        fn assert_send<T: Send>() {}
+        assert_send::<SSHFileSystem<'static>>();
+        assert_send::<SSHFile<'static>>();
+        assert_send::<SSHFileReader<'static>>();
+    }
+
+    #[test]
+    fn test_parse_stat_output() {
+        let metadata = parse_stat_output("regular file|9|1570000000|644").unwrap();
+        assert_eq!(metadata.file_type, FileType::File);
+        assert_eq!(metadata.len, 9);
+        assert_eq!(
+            metadata.modified.unwrap(),
+            UNIX_EPOCH + Duration::from_secs(1570000000)
+        );
+        assert_eq!(metadata.permissions.unwrap().mode(), 0o644);
+
+        assert!(parse_stat_output("directory|4096|1570000000|755")
+            .unwrap()
+            .is_dir());
+        assert!(parse_stat_output("No such file or directory").is_none());
+    }
+
+    #[test]
+    fn test_parse_find_output() {
+        let output = "f|9|1570000000.5000000000|644|foo|users|my file\0\
+                      d|4096|1570000000.0000000000|755|foo|users|dir|with|pipes\0\
+                      l|7|1570000000|777|root|root|new\nline\0";
+        let entries = parse_find_output(Path::new("share"), output).unwrap();
+        assert_eq!(entries.len(), 3);
+
+        let file = entries.first().unwrap();
+        assert_eq!(file.path().unwrap(), PathBuf::from("share/my file"));
+        assert_eq!(file.metadata().len, 9);
+        assert_eq!(
+            file.metadata().modified.unwrap(),
+            UNIX_EPOCH + Duration::from_millis(1570000000500)
+        );
+        assert_eq!(file.metadata().permissions.as_ref().unwrap().mode(), 0o644);
+        assert_eq!(file.owner(), Some("foo"));
+        assert_eq!(file.group(), Some("users"));
+
+        let dir = entries.get(1).unwrap();
+        assert_eq!(dir.file_type().unwrap(), FileType::Directory);
+        assert_eq!(dir.name().unwrap(), "dir|with|pipes");
+
+        let symlink = entries.get(2).unwrap();
+        assert_eq!(symlink.file_type().unwrap(), FileType::Symlink);
+        assert_eq!(symlink.name().unwrap(), "new\nline");
+
+        assert!(parse_find_output(Path::new("share"), "")
+            .unwrap()
+            .is_empty());
+        assert!(parse_find_output(Path::new("share"), "find: permission denied\0").is_none());
+    }
+
+    #[test]
+    fn test_parse_stat_listing() {
+        let output = "regular file|9|1570000000|644|foo|users|share/./my file\n\0\
+                      directory|4096|1570000000|755|foo|users|share/./dir|with|pipes\n\0\
+                      regular file|0|1570000000|644|foo|users|share/./new\nline\n\0";
+        let entries = parse_stat_listing(Path::new("share"), output).unwrap();
+        assert_eq!(entries.len(), 3);
+
+        let file = entries.first().unwrap();
+        assert_eq!(file.path().unwrap(), PathBuf::from("share/my file"));
+        assert_eq!(file.metadata().len, 9);
+        assert_eq!(file.metadata().permissions.as_ref().unwrap().mode(), 0o644);
+        assert_eq!(file.owner(), Some("foo"));
+        assert_eq!(file.group(), Some("users"));
+
+        let dir = entries.get(1).unwrap();
+        assert_eq!(dir.file_type().unwrap(), FileType::Directory);
+        assert_eq!(dir.name().unwrap(), "dir|with|pipes");
+        assert_eq!(entries.get(2).unwrap().name().unwrap(), "new\nline");
+
+        assert!(parse_stat_listing(Path::new("share"), "stat: unknown option\n").is_none());
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("share/my-file_1.txt"), "share/my-file_1.txt");
+        assert_eq!(shell_quote(""), "''");
+        assert_eq!(shell_quote("my file"), "'my file'");
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+        assert_eq!(
+            ShellCommand::new("rm")
+                .arg("-rf")
+                .end_of_options()
+                .arg("; rm -rf ~")
+                .and(ShellCommand::new("ls"))
+                .as_str(),
+            "rm -rf -- '; rm -rf ~' && ls"
+        );
+    }
+
+    #[test]
+    fn test_shell_quote_hostile_names() {
+        let names = [
+            "my file",
+            "it's",
+            "\"double\"",
+            "; touch pwned",
+            "$(touch pwned)",
+            "`touch pwned`",
+            "a\\b",
+            "-rf",
+            "*",
+            "new\nline",
+            "",
+        ];
+        for name in names.iter() {
+            let command = ShellCommand::new("printf").arg("%s").arg(name);
+            let output = std::process::Command::new("sh")
+                .arg("-c")
+                .arg(command.as_str())
+                .output()
+                .unwrap();
+            assert!(output.status.success());
+            assert_eq!(String::from_utf8(output.stdout).unwrap(), *name);
+        }
+    }
+
+    #[test]
+    fn test_shell_quote_non_utf8_path() {
+        let names: [&[u8]; 3] = [b"my\xfffile", b"it's \xfe%d\\n", b"-\xff"];
+        for name in names.iter() {
+            let command = ShellCommand::new("printf")
+                .arg("%s")
+                .path(Path::new(std::ffi::OsStr::from_bytes(name)));
+            let output = std::process::Command::new("sh")
+                .arg("-c")
+                .arg(command.as_str())
+                .output()
+                .unwrap();
+            assert!(output.status.success());
+            assert_eq!(output.stdout, *name);
+        }
+    }
+
     #[test]
     fn test_create_dir() {
         let ssh_fs = SSHFileSystem::new(
@@ -575,8 +1279,11 @@ mod tests {
         file_created.sync_all().unwrap();
 
         let mut file = ssh_fs.open_file("share/testsshfull.test").unwrap();
+        assert_eq!(file.size(), 16);
+        assert_eq!(file.mode(), 0o755);
         let mut buffer = String::new();
         file.read_to_string(&mut buffer).unwrap();
+        assert_eq!(buffer, "Coucou c'est moi");
         ssh_fs.remove_file("share/testsshfull.test").unwrap();
     }
 
@@ -598,6 +1305,111 @@ mod tests {
         ssh_fs.remove_dir_all("share/testsshremovedirtest").unwrap();
     }
 
+    #[test]
+    fn test_copy() {
+        let ssh_fs = SSHFileSystem::new(
+            String::from("127.0.0.1:22"),
+            env::var("SSH_USER").expect("SSH_USER environment variable must be set"),
+            None,
+            env::var("SSH_PRIVATE_KEY").expect("SSH_PRIVATE_KEY environment variable must be set"),
+            env::var("SSH_PUBLIC_KEY").expect("SSH_PUBLIC_KEY environment variable must be set"),
+        );
+        {
+            let mut file = ssh_fs.create_file("share/testsshcopy.test").unwrap();
+            file.write_all(b"coucoutoi").unwrap();
+            file.sync_all().unwrap();
+        }
+
+        ssh_fs
+            .copy("share/testsshcopy.test", "share/testsshcopybis.test")
+            .unwrap();
+
+        let mut content = String::new();
+        let mut file = ssh_fs.open_file("share/testsshcopybis.test").unwrap();
+        file.read_to_string(&mut content).unwrap();
+        assert_eq!(String::from("coucoutoi"), content);
+
+        ssh_fs.remove_file("share/testsshcopy.test").unwrap();
+        ssh_fs.remove_file("share/testsshcopybis.test").unwrap();
+    }
+
+    #[test]
+    fn test_symlink() {
+        let ssh_fs = SSHFileSystem::new(
+            String::from("127.0.0.1:22"),
+            env::var("SSH_USER").expect("SSH_USER environment variable must be set"),
+            None,
+            env::var("SSH_PRIVATE_KEY").expect("SSH_PRIVATE_KEY environment variable must be set"),
+            env::var("SSH_PUBLIC_KEY").expect("SSH_PUBLIC_KEY environment variable must be set"),
+        );
+        ssh_fs.create_file("share/testsshsymlink.test").unwrap();
+
+        ssh_fs
+            .symlink("testsshsymlink.test", "share/testsshsymlinkbis.test")
+            .unwrap();
+
+        assert_eq!(
+            ssh_fs.read_link("share/testsshsymlinkbis.test").unwrap(),
+            PathBuf::from("testsshsymlink.test")
+        );
+        assert!(ssh_fs
+            .metadata("share/testsshsymlinkbis.test")
+            .unwrap()
+            .is_file());
+        assert_eq!(
+            ssh_fs
+                .symlink_metadata("share/testsshsymlinkbis.test")
+                .unwrap()
+                .file_type,
+            FileType::Symlink
+        );
+
+        ssh_fs.remove_file("share/testsshsymlinkbis.test").unwrap();
+        ssh_fs.remove_file("share/testsshsymlink.test").unwrap();
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        let ssh_fs = SSHFileSystem::new(
+            String::from("127.0.0.1:22"),
+            env::var("SSH_USER").expect("SSH_USER environment variable must be set"),
+            None,
+            env::var("SSH_PRIVATE_KEY").expect("SSH_PRIVATE_KEY environment variable must be set"),
+            env::var("SSH_PUBLIC_KEY").expect("SSH_PUBLIC_KEY environment variable must be set"),
+        );
+
+        let share = ssh_fs.canonicalize("share").unwrap();
+        assert!(share.is_absolute());
+        assert_eq!(ssh_fs.canonicalize("share/../share/.").unwrap(), share);
+        assert!(ssh_fs
+            .canonicalize("share/testcanonicalizemissing")
+            .is_err());
+    }
+
+    #[test]
+    fn test_checksum() {
+        let ssh_fs = SSHFileSystem::new(
+            String::from("127.0.0.1:22"),
+            env::var("SSH_USER").expect("SSH_USER environment variable must be set"),
+            None,
+            env::var("SSH_PRIVATE_KEY").expect("SSH_PRIVATE_KEY environment variable must be set"),
+            env::var("SSH_PUBLIC_KEY").expect("SSH_PUBLIC_KEY environment variable must be set"),
+        );
+        {
+            let mut file = ssh_fs.create_file("share/testsshchecksum.test").unwrap();
+            file.write_all(b"coucoutoi").unwrap();
+            file.sync_all().unwrap();
+        }
+
+        assert_eq!(
+            ssh_fs
+                .checksum("share/testsshchecksum.test", ChecksumAlgorithm::Md5)
+                .unwrap(),
+            String::from("9b7c77a17e4906f5ed3fb8afdc129cfb")
+        );
+        ssh_fs.remove_file("share/testsshchecksum.test").unwrap();
+    }
+
     #[test]
     fn test_seek_file() {
         let ssh_fs = SSHFileSystem::new(
@@ -625,6 +1437,78 @@ mod tests {
         ssh_fs.remove_file("testseek.test").unwrap();
     }
 
+    #[test]
+    fn test_exec() {
+        let ssh_fs = SSHFileSystem::new(
+            String::from("127.0.0.1:22"),
+            env::var("SSH_USER").expect("SSH_USER environment variable must be set"),
+            None,
+            env::var("SSH_PRIVATE_KEY").expect("SSH_PRIVATE_KEY environment variable must be set"),
+            env::var("SSH_PUBLIC_KEY").expect("SSH_PUBLIC_KEY environment variable must be set"),
+        )
+        .command_timeout(Duration::from_secs(1));
+
+        let output = ssh_fs.exec("cat; echo oops >&2", Some(b"coucou")).unwrap();
+        assert_eq!(output.stdout, b"coucou");
+        assert_eq!(output.stderr, b"oops\n");
+        assert_eq!(output.exit_status, 0);
+
+        let output = ssh_fs.exec("exit 3", None).unwrap();
+        assert_eq!(output.exit_status, 3);
+        assert!(output.check().is_err());
+
+        assert!(ssh_fs.exec("sleep 5", None).is_err());
+    }
+
+    #[test]
+    fn test_exec_output_check() {
+        let output = ExecOutput {
+            stdout: b"ok".to_vec(),
+            stderr: Vec::new(),
+            exit_status: 0,
+        };
+        assert_eq!(output.check().unwrap().stdout, b"ok");
+
+        let output = ExecOutput {
+            stdout: Vec::new(),
+            stderr: b"no such file".to_vec(),
+            exit_status: 1,
+        };
+        match output.check() {
+            Err(ChiconError::SSHExecutionError(message)) => assert_eq!(message, "no such file"),
+            _ => panic!("expected an execution error"),
+        }
+    }
+
+    #[test]
+    fn test_open_reader() {
+        let ssh_fs = SSHFileSystem::new(
+            String::from("127.0.0.1:22"),
+            env::var("SSH_USER").expect("SSH_USER environment variable must be set"),
+            None,
+            env::var("SSH_PRIVATE_KEY").expect("SSH_PRIVATE_KEY environment variable must be set"),
+            env::var("SSH_PUBLIC_KEY").expect("SSH_PUBLIC_KEY environment variable must be set"),
+        );
+        {
+            let mut file = ssh_fs.create_file("share/testsshreader.test").unwrap();
+            file.write_all(b"coucoutoi").unwrap();
+            file.sync_all().unwrap();
+        }
+
+        let mut reader = ssh_fs.open_reader("share/testsshreader.test").unwrap();
+        assert_eq!(reader.metadata().len, 9);
+        reader.seek(SeekFrom::Start(2)).unwrap();
+        let mut content = String::new();
+        reader.read_to_string(&mut content).unwrap();
+        assert_eq!(String::from("ucoutoi"), content);
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        let mut buf = [0u8; 4];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"couc");
+
+        ssh_fs.remove_file("share/testsshreader.test").unwrap();
+    }
+
     #[test]
     fn test_seek_end_file() {
         let ssh_fs = SSHFileSystem::new(
@@ -0,0 +1,316 @@
+use std::fs::Permissions;
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::error::ChiconError;
+use crate::{ChecksumAlgorithm, DirEntry, File, FileSystem, FileType, Metadata, VirtualPath};
+
+/// Structure implementing `FileSystem` trait to expose only a sub-directory of another filesystem, like a chroot.
+///
+/// Every path is relative to `base`, `/` being `base` itself, and paths escaping `base` are refused.
+pub struct SubFileSystem<F: FileSystem> {
+    inner: F,
+    base: VirtualPath,
+}
+
+impl<F: FileSystem> SubFileSystem<F> {
+    pub fn new<P: AsRef<Path>>(inner: F, base: P) -> Result<Self, ChiconError> {
+        Ok(SubFileSystem {
+            inner,
+            base: VirtualPath::new(base)?,
+        })
+    }
+
+    /// Get back the wrapped filesystem
+    pub fn into_inner(self) -> F {
+        self.inner
+    }
+
+    fn inner_path<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, ChiconError> {
+        let path = VirtualPath::new(path)?;
+        Ok(self.base.join(path.relative())?.to_path_buf())
+    }
+
+    // Translate a path of the wrapped filesystem to a path of this filesystem
+    fn outer_path(&self, inner_path: &Path, base: &Path) -> Result<PathBuf, ChiconError> {
+        let relative = inner_path
+            .strip_prefix(base)
+            .map_err(|_| ChiconError::PathOutsideRoot(PathBuf::from(inner_path)))?;
+
+        Ok(Path::new("/").join(relative))
+    }
+}
+
+impl<F> FileSystem for SubFileSystem<F>
+where
+    F: FileSystem,
+    ChiconError: From<F::FSError>
+        + From<<F::File as File>::FSError>
+        + From<<F::DirEntry as DirEntry>::FSError>,
+{
+    type FSError = ChiconError;
+    type File = F::File;
+    type DirEntry = SubDirEntry<F::DirEntry>;
+
+    fn chmod<P: AsRef<Path>>(&self, path: P, perm: Permissions) -> Result<(), Self::FSError> {
+        Ok(self.inner.chmod(self.inner_path(path)?, perm)?)
+    }
+
+    fn create_file<P: AsRef<Path>>(&self, path: P) -> Result<Self::File, Self::FSError> {
+        Ok(self.inner.create_file(self.inner_path(path)?)?)
+    }
+
+    fn create_dir<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
+        Ok(self.inner.create_dir(self.inner_path(path)?)?)
+    }
+
+    fn create_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
+        Ok(self.inner.create_dir_all(self.inner_path(path)?)?)
+    }
+
+    fn open_file<P: AsRef<Path>>(&self, path: P) -> Result<Self::File, Self::FSError> {
+        Ok(self.inner.open_file(self.inner_path(path)?)?)
+    }
+
+    fn read_dir<P: AsRef<Path>>(&self, path: P) -> Result<Vec<Self::DirEntry>, Self::FSError> {
+        let dir_entries = self.inner.read_dir(self.inner_path(path)?)?;
+        // Inner paths are under the base as given, except for a relative root which they don't start with
+        let base = if self.base.is_root() && !self.base.is_absolute() {
+            PathBuf::new()
+        } else {
+            self.base.to_path_buf()
+        };
+        Ok(dir_entries
+            .into_iter()
+            .map(|entry| SubDirEntry {
+                entry,
+                base: base.clone(),
+            })
+            .collect())
+    }
+
+    fn remove_file<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
+        Ok(self.inner.remove_file(self.inner_path(path)?)?)
+    }
+
+    fn remove_dir<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
+        Ok(self.inner.remove_dir(self.inner_path(path)?)?)
+    }
+
+    fn remove_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<(), Self::FSError> {
+        Ok(self.inner.remove_dir_all(self.inner_path(path)?)?)
+    }
+
+    fn rename<P: AsRef<Path>>(&self, from: P, to: P) -> Result<(), Self::FSError> {
+        Ok(self
+            .inner
+            .rename(self.inner_path(from)?, self.inner_path(to)?)?)
+    }
+
+    fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata, Self::FSError> {
+        Ok(self.inner.metadata(self.inner_path(path)?)?)
+    }
+
+    /// Absolute targets are relative to the base, relative targets must not go above it
+    fn symlink<P: AsRef<Path>>(&self, target: P, link: P) -> Result<(), Self::FSError> {
+        let target = target.as_ref();
+        let link = VirtualPath::new(link)?;
+        let inner_target = if target.has_root() {
+            self.inner_path(target)?
+        } else {
+            let link_parent = link.parent().unwrap_or_else(|| link.clone());
+            link_parent.join(target)?;
+            PathBuf::from(target)
+        };
+
+        Ok(self
+            .inner
+            .symlink(inner_target, self.inner_path(link.relative())?)?)
+    }
+
+    fn read_link<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Self::FSError> {
+        let target = self.inner.read_link(self.inner_path(path)?)?;
+        if target.has_root() {
+            return self.outer_path(&target, &self.base.to_path_buf());
+        }
+
+        Ok(target)
+    }
+
+    fn symlink_metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata, Self::FSError> {
+        Ok(self.inner.symlink_metadata(self.inner_path(path)?)?)
+    }
+
+    /// Resolved paths pointing outside of the base are refused
+    fn canonicalize<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Self::FSError> {
+        let canonical_base = self.inner.canonicalize(self.base.to_path_buf())?;
+        let canonical_path = self.inner.canonicalize(self.inner_path(path)?)?;
+
+        self.outer_path(&canonical_path, &canonical_base)
+    }
+
+    fn set_modified<P: AsRef<Path>>(
+        &self,
+        path: P,
+        modified: SystemTime,
+    ) -> Result<(), Self::FSError> {
+        Ok(self.inner.set_modified(self.inner_path(path)?, modified)?)
+    }
+
+    fn copy<P: AsRef<Path>>(&self, from: P, to: P) -> Result<(), Self::FSError> {
+        Ok(self
+            .inner
+            .copy(self.inner_path(from)?, self.inner_path(to)?)?)
+    }
+
+    fn copy_dir_all<P: AsRef<Path>>(&self, from: P, to: P) -> Result<(), Self::FSError> {
+        Ok(self
+            .inner
+            .copy_dir_all(self.inner_path(from)?, self.inner_path(to)?)?)
+    }
+
+    fn checksum<P: AsRef<Path>>(
+        &self,
+        path: P,
+        algorithm: ChecksumAlgorithm,
+    ) -> Result<String, Self::FSError> {
+        Ok(self.inner.checksum(self.inner_path(path)?, algorithm)?)
+    }
+
+    fn atomic_write<P, W>(&self, path: P, write: W) -> Result<(), Self::FSError>
+    where
+        P: AsRef<Path>,
+        W: FnOnce(&mut Self::File) -> Result<(), Self::FSError>,
+    {
+        // The wrapped filesystem expects its own error type from `write`, keep ours aside
+        let mut write_err: Option<ChiconError> = None;
+        let res = self.inner.atomic_write(self.inner_path(path)?, |file| {
+            write(file).map_err(|err| {
+                write_err = Some(err);
+                F::FSError::from(std::io::Error::other("atomic write aborted"))
+            })
+        });
+        if let Some(err) = write_err {
+            return Err(err);
+        }
+
+        Ok(res?)
+    }
+}
+
+/// Structure implementing `DirEntry` trait to represent an entry of a `SubFileSystem`, its path is relative to the base
+pub struct SubDirEntry<E: DirEntry> {
+    entry: E,
+    base: PathBuf,
+}
+
+impl<E> DirEntry for SubDirEntry<E>
+where
+    E: DirEntry,
+    ChiconError: From<E::FSError>,
+{
+    type FSError = ChiconError;
+
+    fn path(&self) -> Result<PathBuf, Self::FSError> {
+        let path = self.entry.path()?;
+        if let Ok(relative) = path.strip_prefix(&self.base) {
+            return Ok(PathBuf::from(relative));
+        }
+
+        // Some backends only give the name of the entry, any other path would expose the inner one
+        let mut components = path.components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(_)), None) => Ok(path),
+            _ => Err(ChiconError::PathOutsideRoot(path)),
+        }
+    }
+
+    fn file_type(&self) -> Result<FileType, Self::FSError> {
+        Ok(self.entry.file_type()?)
+    }
+
+    fn name(&self) -> Result<String, Self::FSError> {
+        Ok(self.entry.name()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MemFileSystem, OsFileSystem};
+    use std::io::{Read, Write};
+
+    #[test]
+    fn test_sub_filesystem() {
+        let mem_fs = MemFileSystem::new();
+        mem_fs.create_dir_all("tenants/first/test").unwrap();
+        mem_fs.create_dir_all("tenants/second").unwrap();
+        {
+            let mut file = mem_fs.create_file("tenants/second/secret").unwrap();
+            file.write_all(b"secret").unwrap();
+        }
+        let sub_fs = SubFileSystem::new(mem_fs, "tenants/first").unwrap();
+
+        {
+            let mut file = sub_fs.create_file("/test/myfile").unwrap();
+            file.write_all(b"coucoutoi").unwrap();
+        }
+        let mut content = String::new();
+        sub_fs
+            .open_file("test/../test/myfile")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(String::from("coucoutoi"), content);
+        assert!(sub_fs.open_file("../second/secret").is_err());
+        assert!(sub_fs
+            .symlink("../../second/secret", "test/mylink")
+            .is_err());
+
+        let mem_fs = sub_fs.into_inner();
+        assert!(mem_fs.metadata("tenants/first/test/myfile").is_ok());
+    }
+
+    #[test]
+    fn test_sub_filesystem_dir_entries() {
+        let os_fs = OsFileSystem::new();
+        os_fs.create_dir_all("testsubfs/base/test").unwrap();
+        os_fs.create_file("testsubfs/base/test/myfile").unwrap();
+        os_fs.create_file("testsubfs/other").unwrap();
+
+        let sub_fs = SubFileSystem::new(os_fs, "testsubfs/base").unwrap();
+        let dir_entries = sub_fs.read_dir("test").unwrap();
+        assert_eq!(dir_entries.len(), 1);
+        assert_eq!(
+            dir_entries.first().unwrap().path().unwrap(),
+            PathBuf::from("test/myfile")
+        );
+        assert_eq!(
+            sub_fs.canonicalize("test/myfile").unwrap(),
+            PathBuf::from("/test/myfile")
+        );
+
+        std::fs::remove_dir_all("testsubfs").unwrap();
+    }
+
+    #[test]
+    fn test_sub_filesystem_absolute_base() {
+        let os_fs = OsFileSystem::new();
+        os_fs.create_dir_all("testsubfsabsolute/base/test").unwrap();
+        os_fs
+            .create_file("testsubfsabsolute/base/test/myfile")
+            .unwrap();
+
+        let base = std::env::current_dir()
+            .unwrap()
+            .join("testsubfsabsolute/base");
+        let sub_fs = SubFileSystem::new(os_fs, base).unwrap();
+        let dir_entries = sub_fs.read_dir("test").unwrap();
+        assert_eq!(
+            dir_entries.first().unwrap().path().unwrap(),
+            PathBuf::from("test/myfile")
+        );
+
+        std::fs::remove_dir_all("testsubfsabsolute").unwrap();
+    }
+}
@@ -0,0 +1,492 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use glob::Pattern;
+
+use crate::error::ChiconError;
+use crate::transfer::{transfer_file, walk_dir, WalkEntry};
+use crate::{ChecksumAlgorithm, FileSystem, FileType, TransferOptions, TransferProgress};
+
+/// Strategy used to decide if a file already present in the destination must be copied again
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CompareMode {
+    /// Files with a different size are copied
+    Size,
+    /// Files with a different size or a source more recent than the destination are copied
+    SizeAndModified,
+    /// Files with a different size or a different MD5 checksum are copied
+    Checksum,
+}
+
+/// Action planned by `sync_dirs`, paths are relative to the synchronized directories
+#[derive(Clone, Debug, PartialEq)]
+pub enum SyncAction {
+    CreateDir(PathBuf),
+    CopyFile(PathBuf),
+    RemoveFile(PathBuf),
+    RemoveDir(PathBuf),
+}
+
+impl fmt::Display for SyncAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SyncAction::CreateDir(path) => write!(f, "create dir {}", path.display()),
+            SyncAction::CopyFile(path) => write!(f, "copy {}", path.display()),
+            SyncAction::RemoveFile(path) => write!(f, "remove {}", path.display()),
+            SyncAction::RemoveDir(path) => write!(f, "remove dir {}", path.display()),
+        }
+    }
+}
+
+/// List of actions needed to synchronize two directories, in the order they are applied
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SyncPlan {
+    pub actions: Vec<SyncAction>,
+}
+
+impl SyncPlan {
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+}
+
+impl fmt::Display for SyncPlan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for action in &self.actions {
+            writeln!(f, "{}", action)?;
+        }
+        Ok(())
+    }
+}
+
+/// Options to configure `sync_dirs`
+pub struct SyncOptions<'a> {
+    compare: CompareMode,
+    delete: bool,
+    dry_run: bool,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    transfer: TransferOptions<'a>,
+}
+
+impl<'a> Default for SyncOptions<'a> {
+    fn default() -> Self {
+        SyncOptions {
+            compare: CompareMode::SizeAndModified,
+            delete: false,
+            dry_run: false,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            transfer: TransferOptions::default(),
+        }
+    }
+}
+
+impl<'a> SyncOptions<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How files present on both sides are compared, `CompareMode::SizeAndModified` by default
+    pub fn compare(mut self, compare: CompareMode) -> Self {
+        self.compare = compare;
+        self
+    }
+
+    /// Remove files and directories of the destination which don't exist in the source,
+    /// destination entries not matching the include and exclude patterns are kept
+    pub fn delete(mut self, delete: bool) -> Self {
+        self.delete = delete;
+        self
+    }
+
+    /// Only compute the plan without modifying the destination
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Only synchronize files matching this glob pattern, can be called several times
+    pub fn include(mut self, pattern: &str) -> Self {
+        self.include.push(pattern.to_string());
+        self
+    }
+
+    /// Never synchronize nor delete files or directories matching this glob pattern, can be called several times
+    pub fn exclude(mut self, pattern: &str) -> Self {
+        self.exclude.push(pattern.to_string());
+        self
+    }
+
+    /// Options used to copy the files, to report progress for example
+    pub fn transfer_options(mut self, transfer: TransferOptions<'a>) -> Self {
+        self.transfer = transfer;
+        self
+    }
+}
+
+struct Filter {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl Filter {
+    fn new(include: &[String], exclude: &[String]) -> Result<Self, ChiconError> {
+        Ok(Filter {
+            include: include
+                .iter()
+                .map(|pattern| Pattern::new(pattern))
+                .collect::<Result<Vec<Pattern>, _>>()?,
+            exclude: exclude
+                .iter()
+                .map(|pattern| Pattern::new(pattern))
+                .collect::<Result<Vec<Pattern>, _>>()?,
+        })
+    }
+
+    fn is_excluded(&self, path: &Path) -> bool {
+        path.ancestors()
+            .filter(|ancestor| !ancestor.as_os_str().is_empty())
+            .any(|ancestor| self.exclude.iter().any(|p| p.matches_path(ancestor)))
+    }
+
+    fn matches(&self, entry: &WalkEntry) -> bool {
+        if self.is_excluded(&entry.path) {
+            return false;
+        }
+        entry.file_type == FileType::Directory
+            || self.include.is_empty()
+            || self.include.iter().any(|p| p.matches_path(&entry.path))
+    }
+}
+
+/// Synchronize the directory `dst_path` of `dst_fs` with the directory `src_path` of `src_fs`, like rsync does.
+///
+/// Only new and changed files are copied. The returned `SyncPlan` lists the actions which have been applied,
+/// or which would have been applied in dry-run mode.
+pub fn sync_dirs<S, D, P, Q>(
+    src_fs: &S,
+    src_path: P,
+    dst_fs: &D,
+    dst_path: Q,
+    options: &mut SyncOptions,
+) -> Result<SyncPlan, ChiconError>
+where
+    S: FileSystem,
+    D: FileSystem,
+    ChiconError: From<S::FSError> + From<D::FSError>,
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let src_path = src_path.as_ref();
+    let dst_path = dst_path.as_ref();
+    let filter = Filter::new(&options.include, &options.exclude)?;
+
+    let src_entries: Vec<WalkEntry> = walk_dir(src_fs, src_path)?
+        .into_iter()
+        .filter(|entry| filter.matches(entry))
+        .collect();
+    let dst_entries: Vec<WalkEntry> = match dst_fs.metadata(dst_path) {
+        Ok(ref metadata) if metadata.is_dir() => walk_dir(dst_fs, dst_path)?,
+        _ => Vec::new(),
+    };
+    let dst_types: HashMap<&Path, &FileType> = dst_entries
+        .iter()
+        .map(|entry| (entry.path.as_path(), &entry.file_type))
+        .collect();
+
+    let mut plan = SyncPlan::default();
+    for entry in &src_entries {
+        let path = entry.path.clone();
+        let dst_type = dst_types.get(entry.path.as_path());
+        match (&entry.file_type, dst_type) {
+            (FileType::Directory, Some(FileType::Directory)) => {}
+            (FileType::Directory, Some(_)) => {
+                plan.actions.push(SyncAction::RemoveFile(path.clone()));
+                plan.actions.push(SyncAction::CreateDir(path));
+            }
+            (FileType::Directory, None) => plan.actions.push(SyncAction::CreateDir(path)),
+            (_, Some(FileType::Directory)) => {
+                plan.actions.push(SyncAction::RemoveDir(path.clone()));
+                plan.actions.push(SyncAction::CopyFile(path));
+            }
+            (_, Some(_)) => {
+                if has_changed(
+                    src_fs,
+                    &src_path.join(&path),
+                    dst_fs,
+                    &dst_path.join(&path),
+                    options.compare,
+                )? {
+                    plan.actions.push(SyncAction::CopyFile(path));
+                }
+            }
+            (_, None) => plan.actions.push(SyncAction::CopyFile(path)),
+        }
+    }
+
+    if options.delete {
+        let mut removed_dirs: Vec<&Path> = Vec::new();
+        for entry in &dst_entries {
+            let in_source = src_entries.iter().any(|src| src.path == entry.path);
+            // Like rsync, entries out of the include/exclude filter are left untouched
+            if in_source
+                || !filter.matches(entry)
+                || removed_dirs.iter().any(|dir| entry.path.starts_with(dir))
+            {
+                continue;
+            }
+            if entry.file_type == FileType::Directory {
+                // A directory holding filtered out entries is kept, its other entries are removed one by one
+                let keeps_filtered_out = dst_entries
+                    .iter()
+                    .filter(|child| child.path != entry.path && child.path.starts_with(&entry.path))
+                    .any(|child| !filter.matches(child));
+                if !keeps_filtered_out {
+                    removed_dirs.push(entry.path.as_path());
+                    plan.actions.push(SyncAction::RemoveDir(entry.path.clone()));
+                }
+            } else {
+                plan.actions
+                    .push(SyncAction::RemoveFile(entry.path.clone()));
+            }
+        }
+    }
+
+    if !options.dry_run {
+        apply_plan(src_fs, src_path, dst_fs, dst_path, &plan, options)?;
+    }
+
+    Ok(plan)
+}
+
+fn apply_plan<S, D>(
+    src_fs: &S,
+    src_path: &Path,
+    dst_fs: &D,
+    dst_path: &Path,
+    plan: &SyncPlan,
+    options: &mut SyncOptions,
+) -> Result<(), ChiconError>
+where
+    S: FileSystem,
+    D: FileSystem,
+    ChiconError: From<S::FSError> + From<D::FSError>,
+{
+    dst_fs.create_dir_all(dst_path)?;
+
+    let mut progress = TransferProgress::default();
+    for action in &plan.actions {
+        match action {
+            SyncAction::CreateDir(path) => dst_fs.create_dir_all(dst_path.join(path))?,
+            SyncAction::CopyFile(path) => transfer_file(
+                src_fs,
+                &src_path.join(path),
+                dst_fs,
+                &dst_path.join(path),
+                &mut options.transfer,
+                &mut progress,
+            )?,
+            SyncAction::RemoveFile(path) => dst_fs.remove_file(dst_path.join(path))?,
+            SyncAction::RemoveDir(path) => dst_fs.remove_dir_all(dst_path.join(path))?,
+        }
+    }
+
+    Ok(())
+}
+
+fn has_changed<S, D>(
+    src_fs: &S,
+    src_path: &Path,
+    dst_fs: &D,
+    dst_path: &Path,
+    compare: CompareMode,
+) -> Result<bool, ChiconError>
+where
+    S: FileSystem,
+    D: FileSystem,
+    ChiconError: From<S::FSError> + From<D::FSError>,
+{
+    let src_metadata = src_fs.metadata(src_path)?;
+    let dst_metadata = dst_fs.metadata(dst_path)?;
+    if src_metadata.len != dst_metadata.len {
+        return Ok(true);
+    }
+
+    match compare {
+        CompareMode::Size => Ok(false),
+        CompareMode::SizeAndModified => match (src_metadata.modified, dst_metadata.modified) {
+            (Some(src_modified), Some(dst_modified)) => Ok(src_modified > dst_modified),
+            _ => Ok(false),
+        },
+        CompareMode::Checksum => {
+            let src_checksum = src_fs.checksum(src_path, ChecksumAlgorithm::Md5)?;
+            let dst_checksum = dst_fs.checksum(dst_path, ChecksumAlgorithm::Md5)?;
+            Ok(src_checksum != dst_checksum)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{File, MemFileSystem};
+    use std::io::{Read, Write};
+
+    fn write_file(fs: &MemFileSystem, path: &str, content: &[u8]) {
+        let mut file = fs.create_file(path).unwrap();
+        file.write_all(content).unwrap();
+        file.sync_all().unwrap();
+    }
+
+    fn read_file(fs: &MemFileSystem, path: &str) -> String {
+        let mut content = String::new();
+        fs.open_file(path)
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        content
+    }
+
+    #[test]
+    fn test_sync_dirs() {
+        let src_fs = MemFileSystem::new();
+        let dst_fs = MemFileSystem::new();
+        src_fs.create_dir_all("share/testsync/test").unwrap();
+        write_file(&src_fs, "share/testsync/test/myfile", b"coucoutoi");
+        write_file(&src_fs, "share/testsync/myotherfile", b"coucou");
+
+        let plan = sync_dirs(
+            &src_fs,
+            "share/testsync",
+            &dst_fs,
+            "backup",
+            &mut SyncOptions::new(),
+        )
+        .unwrap();
+        assert_eq!(
+            plan.actions,
+            vec![
+                SyncAction::CopyFile(PathBuf::from("myotherfile")),
+                SyncAction::CreateDir(PathBuf::from("test")),
+                SyncAction::CopyFile(PathBuf::from("test/myfile")),
+            ]
+        );
+        assert_eq!(read_file(&dst_fs, "backup/test/myfile"), "coucoutoi");
+
+        // Nothing changed since the last synchronization
+        let plan = sync_dirs(
+            &src_fs,
+            "share/testsync",
+            &dst_fs,
+            "backup",
+            &mut SyncOptions::new(),
+        )
+        .unwrap();
+        assert!(plan.is_empty());
+
+        write_file(&src_fs, "share/testsync/test/myfile", b"toicoucou");
+        let plan = sync_dirs(
+            &src_fs,
+            "share/testsync",
+            &dst_fs,
+            "backup",
+            &mut SyncOptions::new(),
+        )
+        .unwrap();
+        assert_eq!(
+            plan.actions,
+            vec![SyncAction::CopyFile(PathBuf::from("test/myfile"))]
+        );
+        assert_eq!(read_file(&dst_fs, "backup/test/myfile"), "toicoucou");
+    }
+
+    #[test]
+    fn test_sync_dirs_dry_run_and_delete() {
+        let src_fs = MemFileSystem::new();
+        let dst_fs = MemFileSystem::new();
+        src_fs.create_dir_all("share/testsync").unwrap();
+        write_file(&src_fs, "share/testsync/myfile.json", b"{}");
+        write_file(&src_fs, "share/testsync/myfile.log", b"log");
+        dst_fs.create_dir_all("backup/old").unwrap();
+        write_file(&dst_fs, "backup/old/myfile.json", b"old");
+        write_file(&dst_fs, "backup/keep.lock", b"lock");
+
+        let mut options = SyncOptions::new()
+            .delete(true)
+            .dry_run(true)
+            .include("*.json")
+            .exclude("*.lock");
+        let plan = sync_dirs(&src_fs, "share/testsync", &dst_fs, "backup", &mut options).unwrap();
+        assert_eq!(
+            plan.actions,
+            vec![
+                SyncAction::CopyFile(PathBuf::from("myfile.json")),
+                SyncAction::RemoveDir(PathBuf::from("old")),
+            ]
+        );
+        assert_eq!(
+            plan.to_string(),
+            String::from("copy myfile.json\nremove dir old\n")
+        );
+        assert!(dst_fs.open_file("backup/myfile.json").is_err());
+
+        let mut options = SyncOptions::new()
+            .delete(true)
+            .include("*.json")
+            .exclude("*.lock");
+        sync_dirs(&src_fs, "share/testsync", &dst_fs, "backup", &mut options).unwrap();
+        assert_eq!(read_file(&dst_fs, "backup/myfile.json"), "{}");
+        assert!(dst_fs.open_file("backup/myfile.log").is_err());
+        assert!(dst_fs.read_dir("backup/old").is_err());
+        assert_eq!(read_file(&dst_fs, "backup/keep.lock"), "lock");
+    }
+
+    #[test]
+    fn test_sync_dirs_delete_keeps_filtered_out() {
+        let src_fs = MemFileSystem::new();
+        let dst_fs = MemFileSystem::new();
+        src_fs.create_dir_all("share/testsync").unwrap();
+        write_file(&src_fs, "share/testsync/myfile.json", b"{}");
+        dst_fs.create_dir_all("backup/old").unwrap();
+        write_file(&dst_fs, "backup/notes.txt", b"notes");
+        write_file(&dst_fs, "backup/old/myfile.json", b"old");
+        write_file(&dst_fs, "backup/old/notes.txt", b"old notes");
+
+        let mut options = SyncOptions::new().delete(true).include("*.json");
+        let plan = sync_dirs(&src_fs, "share/testsync", &dst_fs, "backup", &mut options).unwrap();
+        assert_eq!(
+            plan.actions,
+            vec![
+                SyncAction::CopyFile(PathBuf::from("myfile.json")),
+                SyncAction::RemoveFile(PathBuf::from("old/myfile.json")),
+            ]
+        );
+        assert_eq!(read_file(&dst_fs, "backup/notes.txt"), "notes");
+        assert_eq!(read_file(&dst_fs, "backup/old/notes.txt"), "old notes");
+        assert!(dst_fs.open_file("backup/old/myfile.json").is_err());
+    }
+
+    #[test]
+    fn test_sync_dirs_checksum() {
+        let src_fs = MemFileSystem::new();
+        let dst_fs = MemFileSystem::new();
+        src_fs.create_dir_all("share").unwrap();
+        dst_fs.create_dir_all("backup").unwrap();
+        write_file(&dst_fs, "backup/myfile", b"toicoucou");
+        write_file(&src_fs, "share/myfile", b"coucoutoi");
+
+        let mut options = SyncOptions::new().compare(CompareMode::Size);
+        let plan = sync_dirs(&src_fs, "share", &dst_fs, "backup", &mut options).unwrap();
+        assert!(plan.is_empty());
+
+        let mut options = SyncOptions::new().compare(CompareMode::Checksum);
+        let plan = sync_dirs(&src_fs, "share", &dst_fs, "backup", &mut options).unwrap();
+        assert_eq!(
+            plan.actions,
+            vec![SyncAction::CopyFile(PathBuf::from("myfile"))]
+        );
+        assert_eq!(read_file(&dst_fs, "backup/myfile"), "coucoutoi");
+    }
+}
@@ -0,0 +1,379 @@
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::ChiconError;
+use crate::{DirEntry, File, FileSystem, FileType};
+
+const DEFAULT_BUFFER_SIZE: usize = 64 * 1024;
+
+type ProgressCallback<'a> = Box<dyn FnMut(&TransferProgress) + 'a>;
+
+/// Progress of a transfer, given to the progress callback after each chunk and each file
+#[derive(Clone, Debug, Default)]
+pub struct TransferProgress {
+    pub bytes_transferred: u64,
+    pub files_transferred: u64,
+    pub current_path: PathBuf,
+}
+
+/// Options to configure a `transfer` between two filesystems
+pub struct TransferOptions<'a> {
+    buffer_size: usize,
+    preserve_permissions: bool,
+    preserve_modified: bool,
+    on_progress: Option<ProgressCallback<'a>>,
+}
+
+impl<'a> Default for TransferOptions<'a> {
+    fn default() -> Self {
+        TransferOptions {
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            preserve_permissions: true,
+            preserve_modified: true,
+            on_progress: None,
+        }
+    }
+}
+
+impl<'a> TransferOptions<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Size of the chunks read from the source file, 64KB by default
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size.max(1);
+        self
+    }
+
+    /// Copy permissions of source files to destination files when both backends support it, `true` by default
+    pub fn preserve_permissions(mut self, preserve_permissions: bool) -> Self {
+        self.preserve_permissions = preserve_permissions;
+        self
+    }
+
+    /// Copy modification times of source files to destination files when both backends support it, `true` by default
+    pub fn preserve_modified(mut self, preserve_modified: bool) -> Self {
+        self.preserve_modified = preserve_modified;
+        self
+    }
+
+    /// Callback called each time a chunk or a file has been transferred
+    pub fn on_progress<F: FnMut(&TransferProgress) + 'a>(mut self, on_progress: F) -> Self {
+        self.on_progress = Some(Box::new(on_progress));
+        self
+    }
+
+    fn report(&mut self, progress: &TransferProgress) {
+        if let Some(on_progress) = self.on_progress.as_mut() {
+            on_progress(progress);
+        }
+    }
+}
+
+/// Entry found when walking a directory, its path is relative to the walked directory
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct WalkEntry {
+    pub(crate) path: PathBuf,
+    pub(crate) file_type: FileType,
+}
+
+/// Copy a file or a whole directory tree from a filesystem to another one, which can use a different backend.
+///
+/// Files are streamed chunk by chunk from `src_fs` to `dst_fs`, the returned `TransferProgress` is the final state of the transfer.
+/// Permissions and modification times are copied when `preserve_permissions` and `preserve_modified` are set.
+/// Backends which can't set them, like S3 for both, report them as unsupported and the transfer goes on,
+/// those destination files keep the default permissions and the time at which they were written.
+pub fn transfer<S, D, P, Q>(
+    src_fs: &S,
+    src_path: P,
+    dst_fs: &D,
+    dst_path: Q,
+    options: &mut TransferOptions,
+) -> Result<TransferProgress, ChiconError>
+where
+    S: FileSystem,
+    D: FileSystem,
+    ChiconError: From<S::FSError> + From<D::FSError>,
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let src_path = src_path.as_ref();
+    let dst_path = dst_path.as_ref();
+    let mut progress = TransferProgress::default();
+
+    if src_fs.metadata(src_path)?.is_dir() {
+        dst_fs.create_dir_all(dst_path)?;
+        for entry in walk_dir(src_fs, src_path)? {
+            let src_entry_path = src_path.join(&entry.path);
+            let dst_entry_path = dst_path.join(&entry.path);
+            match entry.file_type {
+                FileType::Directory => dst_fs.create_dir_all(dst_entry_path)?,
+                FileType::File | FileType::Symlink => transfer_file(
+                    src_fs,
+                    &src_entry_path,
+                    dst_fs,
+                    &dst_entry_path,
+                    options,
+                    &mut progress,
+                )?,
+            }
+        }
+    } else {
+        transfer_file(src_fs, src_path, dst_fs, dst_path, options, &mut progress)?;
+    }
+
+    Ok(progress)
+}
+
+pub(crate) fn transfer_file<S, D>(
+    src_fs: &S,
+    src_path: &Path,
+    dst_fs: &D,
+    dst_path: &Path,
+    options: &mut TransferOptions,
+    progress: &mut TransferProgress,
+) -> Result<(), ChiconError>
+where
+    S: FileSystem,
+    D: FileSystem,
+    ChiconError: From<S::FSError> + From<D::FSError>,
+{
+    progress.current_path = PathBuf::from(src_path);
+
+    let mut src_file = src_fs.open_file(src_path)?;
+    let mut dst_file = dst_fs.create_file(dst_path)?;
+    let mut buffer = vec![0; options.buffer_size];
+    loop {
+        let nb = src_file.read(&mut buffer)?;
+        if nb == 0 {
+            break;
+        }
+        dst_file.write_all(&buffer[..nb])?;
+        progress.bytes_transferred += nb as u64;
+        options.report(progress);
+    }
+    dst_file.sync_all().map_err(D::FSError::from)?;
+
+    if options.preserve_permissions || options.preserve_modified {
+        let metadata = src_fs.metadata(src_path)?;
+        if let Some(perm) = metadata
+            .permissions
+            .filter(|_| options.preserve_permissions)
+        {
+            skip_unsupported(dst_fs.chmod(dst_path, perm).map_err(ChiconError::from))?;
+        }
+        if let Some(modified) = metadata.modified.filter(|_| options.preserve_modified) {
+            skip_unsupported(
+                dst_fs
+                    .set_modified(dst_path, modified)
+                    .map_err(ChiconError::from),
+            )?;
+        }
+    }
+
+    progress.files_transferred += 1;
+    options.report(progress);
+
+    Ok(())
+}
+
+// Ignore the failure of an operation the backend doesn't support
+fn skip_unsupported(res: Result<(), ChiconError>) -> Result<(), ChiconError> {
+    match res {
+        Err(ChiconError::Unsupported(_)) => Ok(()),
+        Err(ChiconError::IOError(ref err)) if err.kind() == std::io::ErrorKind::Unsupported => {
+            Ok(())
+        }
+        res => res,
+    }
+}
+
+/// Recursively list all entries of a directory, parents always come before their children.
+///
+/// Some backends like S3 already list nested entries, directories are only walked when their content isn't part of the listing.
+pub(crate) fn walk_dir<F>(fs: &F, root: &Path) -> Result<Vec<WalkEntry>, ChiconError>
+where
+    F: FileSystem,
+    ChiconError: From<F::FSError>,
+{
+    let mut entries: Vec<WalkEntry> = Vec::new();
+    walk_dir_internal(fs, root, PathBuf::new(), &mut entries)?;
+
+    Ok(entries)
+}
+
+fn walk_dir_internal<F>(
+    fs: &F,
+    root: &Path,
+    relative_dir: PathBuf,
+    entries: &mut Vec<WalkEntry>,
+) -> Result<(), ChiconError>
+where
+    F: FileSystem,
+    ChiconError: From<F::FSError>,
+{
+    let dir = root.join(&relative_dir);
+    let mut children: Vec<WalkEntry> = Vec::new();
+    for entry in fs.read_dir(&dir)? {
+        let path = entry.path().map_err(F::FSError::from)?;
+        let file_type = entry.file_type().map_err(F::FSError::from)?;
+        let relative_path = match relative_to(&path, &dir) {
+            Some(relative_path) if relative_path.as_os_str().is_empty() => continue,
+            Some(relative_path) => relative_dir.join(relative_path),
+            None => relative_dir.join(entry.name().map_err(F::FSError::from)?),
+        };
+        if children.iter().all(|child| child.path != relative_path) {
+            children.push(WalkEntry {
+                path: relative_path,
+                file_type,
+            });
+        }
+    }
+    // Parents must be created before their children
+    children.sort_by(|a, b| a.path.cmp(&b.path));
+
+    for child in &children {
+        entries.push(child.clone());
+        let already_listed = children
+            .iter()
+            .any(|other| other.path != child.path && other.path.starts_with(&child.path));
+        if child.file_type == FileType::Directory && !already_listed {
+            walk_dir_internal(fs, root, child.path.clone(), entries)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn relative_to(path: &Path, dir: &Path) -> Option<PathBuf> {
+    if let Ok(relative_path) = path.strip_prefix(dir) {
+        return Some(PathBuf::from(relative_path));
+    }
+    // Some backends list entries of "." or "./dir" without the leading "./"
+    let dir = dir.strip_prefix(".").ok()?;
+    path.strip_prefix(dir).ok().map(PathBuf::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MemFileSystem, OsFileSystem};
+
+    #[test]
+    fn test_transfer_file() {
+        let src_fs = MemFileSystem::new();
+        let dst_fs = MemFileSystem::new();
+        {
+            let mut file = src_fs.create_file("test.test").unwrap();
+            file.write_all(b"coucoutoi").unwrap();
+        }
+
+        let progress = transfer(
+            &src_fs,
+            "test.test",
+            &dst_fs,
+            "testbis.test",
+            &mut TransferOptions::new(),
+        )
+        .unwrap();
+        assert_eq!(progress.bytes_transferred, 9);
+        assert_eq!(progress.files_transferred, 1);
+
+        let mut content = String::new();
+        dst_fs
+            .open_file("testbis.test")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(String::from("coucoutoi"), content);
+    }
+
+    #[test]
+    fn test_transfer_dir_with_progress() {
+        let src_fs = MemFileSystem::new();
+        let dst_fs = OsFileSystem::new();
+        src_fs.create_dir_all("share/testtransfer/test").unwrap();
+        {
+            let mut file = src_fs
+                .create_file("share/testtransfer/test/myfile")
+                .unwrap();
+            file.write_all(b"coucoutoi").unwrap();
+            let mut file = src_fs
+                .create_file("share/testtransfer/myotherfile")
+                .unwrap();
+            file.write_all(b"coucou").unwrap();
+        }
+        let modified = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1570000000);
+        src_fs
+            .set_modified("share/testtransfer/myotherfile", modified)
+            .unwrap();
+
+        let mut paths: Vec<PathBuf> = Vec::new();
+        let progress = {
+            let mut options = TransferOptions::new()
+                .buffer_size(4)
+                .on_progress(|progress| paths.push(progress.current_path.clone()));
+            transfer(
+                &src_fs,
+                "share/testtransfer",
+                &dst_fs,
+                "testtransferdir",
+                &mut options,
+            )
+            .unwrap()
+        };
+
+        assert_eq!(progress.bytes_transferred, 15);
+        assert_eq!(progress.files_transferred, 2);
+        assert!(paths.contains(&PathBuf::from("share/testtransfer/test/myfile")));
+        assert_eq!(
+            std::fs::read_to_string("testtransferdir/test/myfile").unwrap(),
+            String::from("coucoutoi")
+        );
+        assert_eq!(
+            std::fs::read_to_string("testtransferdir/myotherfile").unwrap(),
+            String::from("coucou")
+        );
+        assert_eq!(
+            dst_fs
+                .metadata("testtransferdir/myotherfile")
+                .unwrap()
+                .modified,
+            Some(modified)
+        );
+
+        std::fs::remove_dir_all("testtransferdir").unwrap();
+    }
+
+    #[test]
+    fn test_walk_dir() {
+        let mem_fs = MemFileSystem::new();
+        mem_fs.create_dir_all("share/testwalk/test/other").unwrap();
+        mem_fs.create_file("share/testwalk/test/myfile").unwrap();
+        mem_fs.create_file("share/testwalk/myfile").unwrap();
+
+        let entries = walk_dir(&mem_fs, Path::new("share/testwalk")).unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                WalkEntry {
+                    path: PathBuf::from("myfile"),
+                    file_type: FileType::File
+                },
+                WalkEntry {
+                    path: PathBuf::from("test"),
+                    file_type: FileType::Directory
+                },
+                WalkEntry {
+                    path: PathBuf::from("test/myfile"),
+                    file_type: FileType::File
+                },
+                WalkEntry {
+                    path: PathBuf::from("test/other"),
+                    file_type: FileType::Directory
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,228 @@
+use std::ffi::OsStr;
+use std::fmt;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path, PathBuf};
+
+use crate::error::ChiconError;
+
+/// Backend-neutral path, normalized lexically.
+///
+/// `.` components, leading `./` and trailing `/` are removed and `..` components are resolved lexically,
+/// a path going above its root is rejected. It is used by backends storing paths as keys, like S3 and the
+/// in-memory filesystem, and by the wrappers; native backends (local disk, SFTP, SSH) don't use it and keep
+/// the paths as given so that relative paths and symbolic links are resolved by the server.
+///
+/// Names which aren't valid UTF-8 are kept as is by `to_path_buf`, `to_str` and keys encode each invalid byte
+/// as `%XX` and each `%` as `%25`, so that `from_key` gives the original path back.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct VirtualPath {
+    absolute: bool,
+    // Normalized path without root, empty for the root itself
+    relative: PathBuf,
+}
+
+impl VirtualPath {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, ChiconError> {
+        let path = path.as_ref();
+        let mut relative = PathBuf::new();
+        for component in path.components() {
+            match component {
+                Component::ParentDir => {
+                    if !relative.pop() {
+                        return Err(ChiconError::PathOutsideRoot(PathBuf::from(path)));
+                    }
+                }
+                Component::Normal(name) => relative.push(name),
+                Component::RootDir | Component::CurDir | Component::Prefix(_) => {}
+            }
+        }
+
+        Ok(VirtualPath {
+            absolute: path.has_root(),
+            relative,
+        })
+    }
+
+    pub fn is_absolute(&self) -> bool {
+        self.absolute
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.relative.as_os_str().is_empty()
+    }
+
+    /// Normalized path without its leading `/`, empty for the root
+    pub fn relative(&self) -> &Path {
+        self.relative.as_path()
+    }
+
+    /// Path for backends working with native paths, the root is `.` or `/`
+    pub fn to_path_buf(&self) -> PathBuf {
+        if self.absolute {
+            Path::new("/").join(&self.relative)
+        } else if self.is_root() {
+            PathBuf::from(".")
+        } else {
+            self.relative.clone()
+        }
+    }
+
+    /// UTF-8 form of `to_path_buf`
+    pub fn to_str(&self) -> String {
+        encode_non_utf8(&self.to_path_buf())
+    }
+
+    /// Object key of a file, relative to the root of the bucket
+    pub fn to_key(&self) -> String {
+        encode_non_utf8(&self.relative)
+    }
+
+    /// Object key of a directory, ending with `/` except for the root which is empty
+    pub fn to_dir_key(&self) -> String {
+        let mut key = self.to_key();
+        if !key.is_empty() {
+            key.push('/');
+        }
+
+        key
+    }
+
+    /// Path of a key made by `to_key` or `to_dir_key`, with its `%XX` sequences decoded
+    pub fn from_key(key: &str) -> PathBuf {
+        let bytes = key.as_bytes();
+        let mut decoded = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+        while i < bytes.len() {
+            let byte = bytes
+                .get(i + 1..i + 3)
+                .filter(|_| bytes[i] == b'%')
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            match byte {
+                Some(byte) => {
+                    decoded.push(byte);
+                    i += 3;
+                }
+                None => {
+                    decoded.push(bytes[i]);
+                    i += 1;
+                }
+            }
+        }
+
+        PathBuf::from(OsStr::from_bytes(&decoded))
+    }
+
+    pub fn file_name(&self) -> Option<&str> {
+        self.relative.file_name().and_then(|name| name.to_str())
+    }
+
+    pub fn parent(&self) -> Option<VirtualPath> {
+        self.relative.parent().map(|parent| VirtualPath {
+            absolute: self.absolute,
+            relative: PathBuf::from(parent),
+        })
+    }
+
+    pub fn join<P: AsRef<Path>>(&self, path: P) -> Result<VirtualPath, ChiconError> {
+        let path = path.as_ref();
+        if path.has_root() {
+            return VirtualPath::new(path);
+        }
+
+        VirtualPath::new(self.to_path_buf().join(path))
+    }
+}
+
+// Valid UTF-8 sequences are kept as is except `%` written `%25`, and every other byte is written `%XX`
+fn encode_non_utf8(path: &Path) -> String {
+    let mut encoded = String::new();
+    for chunk in path.as_os_str().as_bytes().utf8_chunks() {
+        encoded.push_str(&chunk.valid().replace('%', "%25"));
+        for byte in chunk.invalid() {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+
+    encoded
+}
+
+impl From<VirtualPath> for PathBuf {
+    fn from(path: VirtualPath) -> Self {
+        path.to_path_buf()
+    }
+}
+
+impl fmt::Display for VirtualPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_path_buf().display())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        let path = VirtualPath::new("./share/test/../other/./myfile/").unwrap();
+        assert_eq!(path.relative(), Path::new("share/other/myfile"));
+        assert_eq!(path.to_key(), String::from("share/other/myfile"));
+        assert_eq!(path.to_dir_key(), String::from("share/other/myfile/"));
+        assert_eq!(path.file_name(), Some("myfile"));
+
+        let root = VirtualPath::new("./").unwrap();
+        assert!(root.is_root());
+        assert_eq!(root.to_path_buf(), PathBuf::from("."));
+        assert_eq!(root.to_dir_key(), String::new());
+
+        let absolute = VirtualPath::new("/home/../tmp/").unwrap();
+        assert!(absolute.is_absolute());
+        assert_eq!(absolute.to_str(), String::from("/tmp"));
+        assert_eq!(absolute.to_key(), String::from("tmp"));
+    }
+
+    #[test]
+    fn test_reject_outside_root() {
+        assert!(VirtualPath::new("../myfile").is_err());
+        assert!(VirtualPath::new("share/../../myfile").is_err());
+        assert!(VirtualPath::new("/..").is_err());
+        assert!(VirtualPath::new("share")
+            .unwrap()
+            .join("../../myfile")
+            .is_err());
+    }
+
+    #[test]
+    fn test_non_utf8() {
+        let path = VirtualPath::new(Path::new(OsStr::from_bytes(b"share/my\xfffile"))).unwrap();
+        assert_eq!(
+            path.to_path_buf().as_os_str().as_bytes(),
+            b"share/my\xfffile"
+        );
+        assert_eq!(path.to_key(), String::from("share/my%FFfile"));
+        assert_eq!(path.to_str(), String::from("share/my%FFfile"));
+    }
+
+    #[test]
+    fn test_escape_percent() {
+        let invalid = VirtualPath::new(Path::new(OsStr::from_bytes(b"my\xfffile"))).unwrap();
+        let percent = VirtualPath::new("my%FFfile").unwrap();
+        assert_eq!(invalid.to_key(), String::from("my%FFfile"));
+        assert_eq!(percent.to_key(), String::from("my%25FFfile"));
+        assert_ne!(invalid.to_key(), percent.to_key());
+
+        assert_eq!(
+            VirtualPath::from_key(&invalid.to_key()),
+            invalid.to_path_buf()
+        );
+        assert_eq!(
+            VirtualPath::from_key(&percent.to_key()),
+            percent.to_path_buf()
+        );
+        assert_eq!(
+            VirtualPath::from_key(&VirtualPath::new("100%/x").unwrap().to_dir_key()),
+            PathBuf::from("100%/x/")
+        );
+    }
+}